@@ -0,0 +1,190 @@
+//! benchmarks of every search path of the grep library over generated
+//! corpora, so that redesigns can be compared with `cargo bench`
+//! (criterion keeps the previous run and reports the change).
+//! the in-memory searchers are measured next to the quadratic ones they
+//! replaced, which numbered every match by searching contents for its line.
+//!
+//! corpora vary in size (lines) and match density (share of lines
+//! holding the pattern); they are generated from a fixed seed so runs
+//! on different machines search the same text.
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use grep::index::{build_index, query_index};
+use grep::{run_mini_grep, search, Config, Matches};
+
+const PATTERN: &str = "needle";
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+/* the quadratic searchers take minutes on the largest dense corpus */
+const QUADRATIC_MAX_LINES: usize = 10_000;
+/* (name, one matching line every n lines), 0 for none */
+const DENSITIES: [(&str, usize); 3] = [("none", 0), ("sparse", 1_000), ("dense", 2)];
+const WORDS: [&str; 12] = [
+  "rust", "safe", "fast", "productive", "pick", "three", "trust", "me",
+  "nobody", "frog", "bog", "livelong",
+];
+
+/* xorshift, enough to spread words without pulling in a rand crate */
+struct Words(u64);
+
+impl Iterator for Words {
+  type Item = &'static str;
+  fn next(&mut self) -> Option<&'static str> {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    Some(WORDS[(self.0 % WORDS.len() as u64) as usize])
+  }
+}
+
+/* the previous searchers, kept as the baseline */
+mod quadratic {
+  use super::Matches;
+
+  pub fn search_case_sensitive(pattern: &str, contents: &str, limit: Option<usize>) -> Matches {
+    let mut lines = Matches::new();
+    contents.lines()
+      .filter(|line| line.contains(pattern))
+      .take(limit.unwrap_or(usize::MAX))
+      .for_each(|line| {
+        let cursor_pos = contents.find(line).unwrap();
+        let line_index = contents[..cursor_pos].lines().count();
+        let column_index = line.find(pattern).unwrap();
+        lines.insert( (line_index, column_index), line.to_string());
+      });
+    lines
+  }
+
+  pub fn search_case_insensitive(pattern: &str, contents: &str, limit: Option<usize>) -> Matches {
+    let mut lines = Matches::new();
+    contents.lines()
+      .filter(|line| {
+        line.to_lowercase()
+          .contains(pattern.to_lowercase().as_str())
+      })
+      .take(limit.unwrap_or(usize::MAX))
+      .for_each(|line| {
+        let cursor_pos = contents.find(line).unwrap();
+        let line_index = contents[..cursor_pos].lines().count();
+        let column_index = line.to_lowercase()
+          .find(pattern.to_lowercase().as_str()).unwrap();
+        lines.insert( (line_index, column_index), line.to_string());
+      });
+    lines
+  }
+}
+
+/* lines of 8 words, every `every`-th one with PATTERN in the middle in
+mixed case for odd lines, so case sensitive and insensitive searches differ */
+fn corpus(lines: usize, every: usize) -> String {
+  let mut words = Words(0x2545F4914F6CDD1D);
+  let mut contents = String::with_capacity(lines * 48);
+  for index in 0..lines {
+    for position in 0..8 {
+      if position == 4 && every != 0 && index % every == 0 {
+        contents.push_str(if index % 2 == 0 { PATTERN } else { "NeEdLe" });
+      } else {
+        contents.push_str(words.next().unwrap());
+      }
+      contents.push(' ');
+    }
+    contents.push('\n');
+  }
+  contents
+}
+
+fn bench_in_memory(c: &mut Criterion) {
+  for (density, every) in DENSITIES {
+    let mut group = c.benchmark_group(format!("search/{}", density));
+    for lines in SIZES {
+      let contents = corpus(lines, every);
+      group.throughput(Throughput::Bytes(contents.len() as u64));
+      if lines == 100_000 {
+        group.sample_size(20);
+      }
+      group.bench_with_input(BenchmarkId::new("case_sensitive", lines), &contents, |b, contents| {
+        b.iter(|| search(black_box(PATTERN), black_box(contents), false, None))
+      });
+      group.bench_with_input(BenchmarkId::new("case_insensitive", lines), &contents, |b, contents| {
+        b.iter(|| search(black_box(PATTERN), black_box(contents), true, None))
+      });
+      group.bench_with_input(BenchmarkId::new("max_count_1", lines), &contents, |b, contents| {
+        b.iter(|| search(black_box(PATTERN), black_box(contents), false, Some(1)))
+      });
+      if lines <= QUADRATIC_MAX_LINES {
+        group.bench_with_input(BenchmarkId::new("quadratic_case_sensitive", lines), &contents, |b, contents| {
+          b.iter(|| quadratic::search_case_sensitive(black_box(PATTERN), black_box(contents), None))
+        });
+        group.bench_with_input(BenchmarkId::new("quadratic_case_insensitive", lines), &contents, |b, contents| {
+          b.iter(|| quadratic::search_case_insensitive(black_box(PATTERN), black_box(contents), None))
+        });
+      }
+    }
+    group.finish();
+  }
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("mini-grep-bench-{}-{}", std::process::id(), name));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+fn config_for(path: &Path) -> Config {
+  Config {
+    file_path: path.to_string_lossy().into_owned(),
+    pattern: String::from(PATTERN),
+    ..Config::default()
+  }
+}
+
+/* reading and decoding: the same text stored in each supported encoding */
+fn bench_files(c: &mut Criterion) {
+  let dir = scratch_dir("files");
+  let contents = corpus(10_000, 1_000);
+  let utf16: Vec<u8> = [0xFF, 0xFE].into_iter()
+    .chain(contents.encode_utf16().flat_map(u16::to_le_bytes))
+    .collect();
+  let files = [
+    ("utf8", contents.as_bytes().to_vec()),
+    ("utf16le", utf16),
+    ("latin1", contents.as_bytes().to_vec()), /* the corpus is ASCII */
+  ];
+  let mut group = c.benchmark_group("run_mini_grep/file");
+  for (name, bytes) in files {
+    let path = dir.join(format!("{}.txt", name));
+    fs::write(&path, &bytes).unwrap();
+    let mut config = config_for(&path);
+    if name == "latin1" {
+      config.encoding = grep::encoding::Encoding::from_label("latin1");
+    }
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function(name, |b| b.iter(|| run_mini_grep(black_box(&config)).unwrap()));
+  }
+  group.finish();
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+/* a tree where few files match: full scan against the trigram index */
+fn bench_directory(c: &mut Criterion) {
+  let dir = scratch_dir("tree");
+  for file in 0..200 {
+    let every = if file % 50 == 0 { 100 } else { 0 };
+    fs::write(dir.join(format!("{:03}.txt", file)), corpus(500, every)).unwrap();
+  }
+  let config = config_for(&dir);
+  build_index(&config).unwrap();
+  let mut group = c.benchmark_group("run_mini_grep/directory");
+  group.sample_size(20);
+  group.bench_function("scan", |b| b.iter(|| run_mini_grep(black_box(&config)).unwrap()));
+  group.bench_function("index_query", |b| b.iter(|| query_index(black_box(&config)).unwrap()));
+  group.finish();
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, bench_in_memory, bench_files, bench_directory);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+#![no_main]
+//! argument parsing must reject anything it does not understand with
+//! an error, never panic. run with -detect_leaks=0: errors such as
+//! --version are leaked on purpose to become &'static str.
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|args: Vec<String>| {
+  let args = std::iter::once(String::from("grep")).chain(args);
+  let _ = grep::Config::new(args, "help");
+});
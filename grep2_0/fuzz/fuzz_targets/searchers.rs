@@ -0,0 +1,21 @@
+#![no_main]
+//! the searchers must not panic on any input, and every match they
+//! report must point inside its line at a character boundary.
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (String, String, bool, Option<u8>)| {
+  let (pattern, contents, case_insensitive, limit) = input;
+  let limit = limit.map(usize::from);
+  let found = grep::search(&pattern, &contents, case_insensitive, limit);
+  if let Some(limit) = limit {
+    assert!(found.len() <= limit);
+  }
+  let lines: Vec<&str> = contents.lines().collect();
+  for ((line, column), text) in found {
+    assert_eq!(lines[line], text);
+    assert!(text.is_char_boundary(column));
+    if !case_insensitive {
+      assert!(text[column..].starts_with(pattern.as_str()));
+    }
+  }
+});
@@ -0,0 +1,210 @@
+//! decoding of non UTF-8 inputs.
+//! files starting with a byte order mark are decoded accordingly,
+//! everything else is read as UTF-8 unless an --encoding is forced.
+//! searches run on the decoded text, so reported columns are byte
+//! offsets in its UTF-8 form, not in the file.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+  Utf8,
+  Utf16Le,
+  Utf16Be,
+  Latin1,      /* ISO-8859-1 */
+  Windows1252, /* Latin-1 with printable characters in 0x80..0xA0 */
+}
+
+/* the input is not valid in the encoding it was decoded with */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+  pub encoding: Encoding,
+  pub offset: usize, /* byte offset in the input of the first invalid sequence */
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid {} data at byte {}", self.encoding, self.offset)
+  }
+}
+
+impl Error for DecodeError {}
+
+impl fmt::Display for Encoding {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Encoding::Utf8 => "utf-8",
+      Encoding::Utf16Le => "utf-16le",
+      Encoding::Utf16Be => "utf-16be",
+      Encoding::Latin1 => "latin1",
+      Encoding::Windows1252 => "windows-1252",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/* code points of windows-1252 bytes 0x80..0xA0. the five undefined
+bytes map to the matching C1 control character, as browsers do. */
+const WINDOWS_1252_HIGH: [u16; 32] = [
+  0x20AC, 0x0081, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021,
+  0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x008D, 0x017D, 0x008F,
+  0x0090, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014,
+  0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x009D, 0x017E, 0x0178,
+];
+
+impl Encoding {
+  /* accepts the usual spellings of each encoding name */
+  pub fn from_label(label: &str) -> Option<Encoding> {
+    match label.to_ascii_lowercase().replace('_', "-").as_str() {
+      "utf-8" | "utf8" => Some(Encoding::Utf8),
+      /* BOM-less UTF-16 from windows tools is little endian */
+      "utf-16" | "utf16" | "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+      "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+      "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" => Some(Encoding::Latin1),
+      "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+      _ => None,
+    }
+  }
+
+  /* encoding announced by a byte order mark, and the length of the mark */
+  pub fn sniff(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+      Some((Encoding::Utf8, 3))
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
+      Some((Encoding::Utf16Le, 2))
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
+      Some((Encoding::Utf16Be, 2))
+    } else {
+      None
+    }
+  }
+
+  /* decodes bytes carrying no byte order mark */
+  pub fn decode(self, bytes: &[u8]) -> Result<String, DecodeError> {
+    match self {
+      Encoding::Utf8 => match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text.to_string()),
+        Err(e) => Err(DecodeError { encoding: self, offset: e.valid_up_to() }),
+      },
+      Encoding::Utf16Le | Encoding::Utf16Be => self.decode_utf16(bytes),
+      Encoding::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+      Encoding::Windows1252 => Ok(bytes.iter()
+        .map(|&b| match b {
+          0x80..=0x9F => char::from_u32(WINDOWS_1252_HIGH[(b - 0x80) as usize] as u32).unwrap(),
+          _ => b as char,
+        })
+        .collect()),
+    }
+  }
+
+  /* like decode, replacing invalid sequences with U+FFFD */
+  pub fn decode_lossy(self, bytes: &[u8]) -> String {
+    match self {
+      Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+      Encoding::Utf16Le | Encoding::Utf16Be => {
+        let units = bytes.chunks(2).map(|pair| match (self, pair) {
+          (Encoding::Utf16Be, [high, low]) => u16::from_be_bytes([*high, *low]),
+          (_, [low, high]) => u16::from_le_bytes([*low, *high]),
+          _ => 0xFFFD, /* odd trailing byte */
+        });
+        char::decode_utf16(units)
+          .map(|decoded| decoded.unwrap_or(char::REPLACEMENT_CHARACTER))
+          .collect()
+      },
+      Encoding::Latin1 | Encoding::Windows1252 => self.decode(bytes).unwrap(),
+    }
+  }
+
+  fn decode_utf16(self, bytes: &[u8]) -> Result<String, DecodeError> {
+    if !bytes.len().is_multiple_of(2) {
+      return Err(DecodeError { encoding: self, offset: bytes.len() - 1 });
+    }
+    let units = bytes.chunks_exact(2).map(|pair| match self {
+      Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+      _ => u16::from_le_bytes([pair[0], pair[1]]),
+    });
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut offset = 0;
+    for decoded in char::decode_utf16(units) {
+      match decoded {
+        Ok(c) => {
+          text.push(c);
+          offset += c.len_utf16() * 2;
+        },
+        Err(_) => return Err(DecodeError { encoding: self, offset }),
+      }
+    }
+    Ok(text)
+  }
+
+  /* length of the prefix of bytes made of complete lines, if there is a newline */
+  pub(crate) fn complete_lines_len(self, bytes: &[u8]) -> Option<usize> {
+    match self {
+      Encoding::Utf16Le => bytes.chunks_exact(2)
+        .rposition(|pair| pair == [b'\n', 0])
+        .map(|unit| unit * 2 + 2),
+      Encoding::Utf16Be => bytes.chunks_exact(2)
+        .rposition(|pair| pair == [0, b'\n'])
+        .map(|unit| unit * 2 + 2),
+      _ => bytes.iter().rposition(|&b| b == b'\n').map(|end| end + 1),
+    }
+  }
+}
+
+/* encoding of an input starting with bytes, and how many of them to skip:
+a forced encoding wins, then the byte order mark, then UTF-8.
+a byte order mark matching the forced encoding is skipped as well. */
+pub fn resolve(bytes: &[u8], forced: Option<Encoding>) -> (Encoding, usize) {
+  match (forced, Encoding::sniff(bytes)) {
+    (Some(encoding), Some((marked, mark_len))) if marked == encoding => (encoding, mark_len),
+    (Some(encoding), _) => (encoding, 0),
+    (None, Some((marked, mark_len))) => (marked, mark_len),
+    (None, None) => (Encoding::Utf8, 0),
+  }
+}
+
+/* decodes a whole input, see resolve */
+pub fn decode(bytes: &[u8], forced: Option<Encoding>) -> Result<String, DecodeError> {
+  let (encoding, skip) = resolve(bytes, forced);
+  encoding.decode(&bytes[skip..])
+}
+
+/* fs::read_to_string, transcoding from the file encoding */
+pub fn read_to_string(path: impl AsRef<Path>, forced: Option<Encoding>) -> Result<String, Box<dyn Error>> {
+  let bytes = fs::read(path)?;
+  Ok(decode(&bytes, forced)?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decode_sniffs_byte_order_marks() {
+    let utf16le = [0xFF, 0xFE, b'h', 0, b'i', 0, 0xE9, 0];
+    assert_eq!("hié", decode(&utf16le, None).unwrap());
+    let utf16be = [0xFE, 0xFF, 0, b'h', 0xD8, 0x3D, 0xDE, 0x00];
+    assert_eq!("h😀", decode(&utf16be, None).unwrap());
+    let utf8 = [0xEF, 0xBB, 0xBF, b'o', b'k'];
+    assert_eq!("ok", decode(&utf8, None).unwrap());
+  }
+
+  #[test]
+  fn decode_forced_single_byte_encodings() {
+    let legacy = [b'c', b'a', b'f', 0xE9, b' ', 0x93, b'x', 0x94];
+    assert_eq!(DecodeError { encoding: Encoding::Utf8, offset: 3 }, decode(&legacy, None).unwrap_err());
+    assert_eq!("café \u{93}x\u{94}", decode(&legacy, Some(Encoding::Latin1)).unwrap());
+    assert_eq!("café \u{201C}x\u{201D}", decode(&legacy, Some(Encoding::Windows1252)).unwrap());
+  }
+
+  #[test]
+  fn decode_reports_unpaired_surrogates() {
+    let broken = [b'a', 0, 0x00, 0xDC];
+    assert_eq!(
+      DecodeError { encoding: Encoding::Utf16Le, offset: 2 },
+      decode(&broken, Some(Encoding::Utf16Le)).unwrap_err()
+    );
+  }
+}
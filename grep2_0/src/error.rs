@@ -0,0 +1,74 @@
+//! errors reported by a search.
+//! in a multi-file search the per file errors (Io, Encoding and
+//! PermissionDenied) do not stop the search: they are collected in
+//! GrepOutput::errors and the remaining files are still searched.
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::encoding::DecodeError;
+
+#[derive(Debug)]
+pub enum GrepError {
+  Io { path: PathBuf, source: io::Error },
+  /* position is the byte offset in the pattern of the offending character */
+  InvalidPattern { pattern: String, position: usize, reason: &'static str },
+  Encoding { path: PathBuf, source: DecodeError },
+  PermissionDenied { path: PathBuf },
+}
+
+impl GrepError {
+  /* classifies an io error met while reading path */
+  pub fn io(path: &Path, source: io::Error) -> GrepError {
+    match source.kind() {
+      io::ErrorKind::PermissionDenied => GrepError::PermissionDenied { path: path.to_path_buf() },
+      _ => GrepError::Io { path: path.to_path_buf(), source },
+    }
+  }
+
+  /* the file the error is about, if any */
+  pub fn path(&self) -> Option<&Path> {
+    match self {
+      GrepError::Io { path, .. }
+      | GrepError::Encoding { path, .. }
+      | GrepError::PermissionDenied { path } => Some(path),
+      GrepError::InvalidPattern { .. } => None,
+    }
+  }
+}
+
+impl fmt::Display for GrepError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      GrepError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+      GrepError::InvalidPattern { pattern, position, reason } => {
+        write!(f, "invalid pattern {:?} at position {}: {}", pattern, position, reason)
+      },
+      GrepError::Encoding { path, source } => write!(f, "{}: {}", path.display(), source),
+      GrepError::PermissionDenied { path } => write!(f, "{}: permission denied", path.display()),
+    }
+  }
+}
+
+impl Error for GrepError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      GrepError::Io { source, .. } => Some(source),
+      GrepError::Encoding { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+/* searches run line by line, so a pattern spanning lines can never match */
+pub(crate) fn validate_pattern(pattern: &str) -> Result<(), GrepError> {
+  match pattern.find(['\n', '\r']) {
+    Some(position) => Err(GrepError::InvalidPattern {
+      pattern: pattern.to_string(),
+      position,
+      reason: "patterns cannot contain line breaks",
+    }),
+    None => Ok(()),
+  }
+}
@@ -0,0 +1,385 @@
+//! on-disk trigram index of a directory tree.
+//! every file keeps the sorted set of byte trigrams found in its
+//! contents (and in its lowercased contents, for case insensitive
+//! queries). a query only opens the files holding every trigram of
+//! the pattern and verifies them with the regular searchers.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::encoding::{self, Encoding};
+use crate::error::validate_pattern;
+use crate::{search_file, sort_files, walk, Config, GrepError, GrepOutput};
+
+/* name of the index file written at the root of the indexed directory */
+pub const INDEX_FILE_NAME: &str = ".mini-grep-index";
+const MAGIC: &[u8; 8] = b"MGIDX01\n";
+
+struct Entry {
+  modified: SystemTime,
+  size: u64,
+  trigrams: Vec<u32>,        /* sorted, from the raw contents */
+  folded_trigrams: Vec<u32>, /* sorted, from the lowercased contents */
+}
+
+pub struct Index {
+  root: PathBuf,
+  encoding: Option<Encoding>, /* forced on every file, as with --encoding */
+  entries: HashMap<PathBuf, Entry>, /* keyed by path relative to root */
+}
+
+/* what an update changed in the index */
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+  pub indexed: usize,   /* new or modified files (re)read */
+  pub unchanged: usize, /* files whose mtime and size did not change */
+  pub removed: usize,   /* files that disappeared since the last update */
+  pub skipped: usize,   /* unreadable or undecodable files */
+}
+
+impl UpdateSummary {
+  pub fn changed(&self) -> bool {
+    self.indexed > 0 || self.removed > 0
+  }
+}
+
+impl Index {
+  pub fn index_path(root: &Path) -> PathBuf {
+    root.join(INDEX_FILE_NAME)
+  }
+
+  /* the index saved in root, or an empty one if there is none yet.
+  files are decoded like a search with the same encoding would. */
+  pub fn open(root: &Path, encoding: Option<Encoding>) -> Result<Index, GrepError> {
+    let path = Index::index_path(root);
+    match fs::read(&path) {
+      Ok(bytes) => Index::read_from(root, encoding, &bytes).map_err(|e| GrepError::io(&path, e)),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Index {
+        root: root.to_path_buf(),
+        encoding,
+        entries: HashMap::new(),
+      }),
+      Err(e) => Err(GrepError::io(&path, e)),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /* brings the index in line with the directory, only re-reading
+  files whose modification time or size changed. */
+  pub fn update(&mut self) -> Result<UpdateSummary, GrepError> {
+    let mut summary = UpdateSummary::default();
+    let mut files = Vec::new();
+    let mut unreadable = Vec::new();
+    walk(&self.root, &mut files, &mut unreadable)?;
+    summary.skipped += unreadable.len();
+    let mut seen: HashSet<PathBuf> = HashSet::with_capacity(files.len());
+    for path in files {
+      /* walk only lists paths below the root */
+      let relative = path.strip_prefix(&self.root).unwrap().to_path_buf();
+      let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(_) => {
+          summary.skipped += 1;
+          continue;
+        },
+      };
+      let modified = metadata.modified().map_err(|e| GrepError::io(&path, e))?;
+      let size = metadata.len();
+      if let Some(entry) = self.entries.get(&relative) {
+        if entry.modified == modified && entry.size == size {
+          summary.unchanged += 1;
+          seen.insert(relative);
+          continue;
+        }
+      }
+      match encoding::read_to_string(&path, self.encoding) {
+        Ok(contents) => {
+          let entry = Entry {
+            modified,
+            size,
+            trigrams: trigrams(contents.as_bytes()),
+            folded_trigrams: trigrams(contents.to_lowercase().as_bytes()),
+          };
+          self.entries.insert(relative.clone(), entry);
+          summary.indexed += 1;
+          seen.insert(relative);
+        },
+        Err(_) => summary.skipped += 1,
+      }
+    }
+    let before = self.entries.len();
+    self.entries.retain(|path, _| seen.contains(path));
+    summary.removed = before - self.entries.len();
+    Ok(summary)
+  }
+
+  /* files that may contain the pattern, sorted by path. patterns
+  shorter than a trigram cannot be narrowed down: every file is a candidate. */
+  pub fn candidates(&self, pattern: &str, case_insensitive: bool) -> Vec<PathBuf> {
+    let wanted = if case_insensitive {
+      trigrams(pattern.to_lowercase().as_bytes())
+    } else {
+      trigrams(pattern.as_bytes())
+    };
+    let mut paths: Vec<PathBuf> = self.entries.iter()
+      .filter(|(_, entry)| {
+        let available = if case_insensitive { &entry.folded_trigrams } else { &entry.trigrams };
+        wanted.iter().all(|t| available.binary_search(t).is_ok())
+      })
+      .map(|(path, _)| self.root.join(path))
+      .collect();
+    paths.sort();
+    paths
+  }
+
+  pub fn save(&self) -> Result<(), GrepError> {
+    /* write next to the final file and rename, so a concurrent
+    query never reads a half written index. */
+    let path = Index::index_path(&self.root);
+    let temporary = path.with_extension("tmp");
+    let write = || -> io::Result<()> {
+      let mut writer = io::BufWriter::new(fs::File::create(&temporary)?);
+      self.write_to(&mut writer)?;
+      writer.into_inner().map_err(|e| e.into_error())?.sync_all()
+    };
+    write().map_err(|e| GrepError::io(&temporary, e))?;
+    fs::rename(&temporary, &path).map_err(|e| GrepError::io(&path, e))
+  }
+
+  fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u64(writer, self.entries.len() as u64)?;
+    let mut paths: Vec<&PathBuf> = self.entries.keys().collect();
+    paths.sort();
+    for path in paths {
+      let entry = &self.entries[path];
+      let name = path.to_string_lossy();
+      write_u64(writer, name.len() as u64)?;
+      writer.write_all(name.as_bytes())?;
+      let since_epoch = entry.modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+      write_u64(writer, since_epoch.as_secs())?;
+      write_u64(writer, since_epoch.subsec_nanos() as u64)?;
+      write_u64(writer, entry.size)?;
+      for set in [&entry.trigrams, &entry.folded_trigrams] {
+        write_u64(writer, set.len() as u64)?;
+        for trigram in set {
+          writer.write_all(&trigram.to_le_bytes()[..3])?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /* lengths are checked against the bytes left before allocating, a
+  corrupt index is reported as InvalidData instead of exhausting memory */
+  fn read_from(root: &Path, encoding: Option<Encoding>, mut reader: &[u8]) -> io::Result<Index> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+      return Err(invalid_data("not a mini-grep index"));
+    }
+    let count = read_u64(&mut reader)?;
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+      let mut name = vec![0u8; read_len(&mut reader, 1)?];
+      reader.read_exact(&mut name)?;
+      let secs = read_u64(&mut reader)?;
+      let nanos = read_u64(&mut reader)? as u32;
+      let size = read_u64(&mut reader)?;
+      let mut sets = [Vec::new(), Vec::new()];
+      for set in sets.iter_mut() {
+        let len = read_len(&mut reader, 3)?;
+        set.reserve(len);
+        for _ in 0..len {
+          let mut bytes = [0u8; 4];
+          reader.read_exact(&mut bytes[..3])?;
+          set.push(u32::from_le_bytes(bytes));
+        }
+      }
+      let [trigrams, folded_trigrams] = sets;
+      let name = String::from_utf8(name).map_err(|_| invalid_data("file name is not UTF-8"))?;
+      entries.insert(PathBuf::from(name), Entry {
+        modified: UNIX_EPOCH + Duration::new(secs, nanos),
+        size,
+        trigrams,
+        folded_trigrams,
+      });
+    }
+    Ok(Index { root: root.to_path_buf(), encoding, entries })
+  }
+}
+
+/* `index build DIR`: create or incrementally refresh the index of the
+directory in config.file_path */
+pub fn build_index(config: &Config) -> Result<UpdateSummary, GrepError> {
+  let root = Path::new(&config.file_path);
+  let mut index = Index::open(root, config.encoding)?;
+  let summary = index.update()?;
+  if summary.changed() || !Index::index_path(root).exists() {
+    index.save()?;
+  }
+  Ok(summary)
+}
+
+/* `index query DIR PATTERN`: refresh the index, then verify only the candidate
+files. the stats only count the candidates, not the files left out by the index. */
+pub fn query_index(config: &Config) -> Result<GrepOutput, GrepError> {
+  let started = Instant::now();
+  validate_pattern(&config.pattern)?;
+  let mut output = GrepOutput::default();
+  let dir = &config.file_path;
+  let root = Path::new(dir);
+  let index_path = Index::index_path(root);
+  if !index_path.exists() {
+    let missing = io::Error::new(io::ErrorKind::NotFound, format!("no index, run `index build {}` first", dir));
+    return Err(GrepError::io(&index_path, missing));
+  }
+  let mut index = Index::open(root, config.encoding)?;
+  if index.update()?.changed() {
+    index.save()?;
+  }
+  let mut candidates = index.candidates(&config.pattern, config.case_insensitive);
+  sort_files(&mut candidates, config.sort)?;
+  for path in candidates {
+    match search_file(&path, config, &mut output.stats, true) {
+      Ok(lines) if lines.is_empty() => {},
+      Ok(lines) => output.matches.push((path, lines)),
+      Err(e) => output.errors.push(e),
+    }
+  }
+  output.stats.elapsed = started.elapsed();
+  Ok(output)
+}
+
+/* sorted, deduplicated 3 byte windows packed into the low bytes of a u32 */
+fn trigrams(bytes: &[u8]) -> Vec<u32> {
+  let mut set: Vec<u32> = bytes.windows(3)
+    .map(|w| u32::from_le_bytes([w[0], w[1], w[2], 0]))
+    .collect();
+  set.sort_unstable();
+  set.dedup();
+  set
+}
+
+fn write_u64(writer: &mut impl Write, value: u64) -> io::Result<()> {
+  writer.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+  let mut bytes = [0u8; 8];
+  reader.read_exact(&mut bytes)?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+/* the count of item_size byte items that follows, which must fit in what is left */
+fn read_len(reader: &mut &[u8], item_size: usize) -> io::Result<usize> {
+  let len = read_u64(reader)?;
+  if len > (reader.len() / item_size) as u64 {
+    return Err(invalid_data("length past the end of the index"));
+  }
+  Ok(len as usize)
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-grep-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    dir
+  }
+
+  #[test]
+  fn candidates_require_every_trigram() {
+    let dir = temp_dir("index-candidates");
+    fs::write(dir.join("a.txt"), "Rust:\nsafe, fast, productive.").unwrap();
+    fs::write(dir.join("nested/b.txt"), "Trust me.").unwrap();
+    let mut index = Index::open(&dir, None).unwrap();
+    index.update().unwrap();
+    assert_eq!(vec![dir.join("a.txt")], index.candidates("duct", false));
+    assert_eq!(vec![dir.join("nested/b.txt")], index.candidates("Trust", false));
+    assert_eq!(2, index.candidates("RUST", true).len());
+    /* too short to narrow anything down */
+    assert_eq!(2, index.candidates("st", false).len());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn saved_index_round_trips_and_updates_incrementally() {
+    let dir = temp_dir("index-update");
+    fs::write(dir.join("a.txt"), "alpha").unwrap();
+    fs::write(dir.join("nested/b.txt"), "beta").unwrap();
+    let config = Config {
+      file_path: dir.to_string_lossy().into_owned(),
+      pattern: String::from("gamma"),
+      ..Config::default()
+    };
+    let summary = build_index(&config).unwrap();
+    assert_eq!(2, summary.indexed);
+
+    let mut index = Index::open(&dir, None).unwrap();
+    assert_eq!(2, index.len());
+    fs::write(dir.join("nested/b.txt"), "beta gamma").unwrap();
+    fs::remove_file(dir.join("a.txt")).unwrap();
+    let summary = index.update().unwrap();
+    assert_eq!(UpdateSummary { indexed: 1, unchanged: 0, removed: 1, skipped: 0 }, summary);
+
+    let output = query_index(&config).unwrap();
+    assert_eq!(1, output.stats.files_searched);
+    assert_eq!(1, output.matches.len());
+    assert_eq!(dir.join("nested/b.txt"), output.matches[0].0);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn corrupt_index_is_invalid_data() {
+    let dir = temp_dir("index-corrupt");
+    fs::write(dir.join("a.txt"), "alpha").unwrap();
+    let mut index = Index::open(&dir, None).unwrap();
+    index.update().unwrap();
+    index.save().unwrap();
+    let saved = fs::read(Index::index_path(&dir)).unwrap();
+    let invalid = |bytes: &[u8]| {
+      fs::write(Index::index_path(&dir), bytes).unwrap();
+      match Index::open(&dir, None) {
+        Err(GrepError::Io { path, source }) => {
+          assert_eq!(Index::index_path(&dir), path);
+          source.kind()
+        },
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("corrupt index opened"),
+      }
+    };
+    /* magic, entry count, name length, name, mtime, size, trigram count */
+    let name_length = MAGIC.len() + 8;
+    let trigram_count = name_length + 8 + "a.txt".len() + 3 * 8;
+    let with_u64 = |offset: usize, value: u64| {
+      let mut bytes = saved.clone();
+      bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+      bytes
+    };
+    assert_eq!(io::ErrorKind::InvalidData, invalid(&with_u64(name_length, u64::MAX)));
+    assert_eq!(io::ErrorKind::InvalidData, invalid(&with_u64(trigram_count, 1 << 40)));
+    assert_eq!(io::ErrorKind::InvalidData, invalid(b"not an index at all"));
+    assert_eq!(io::ErrorKind::UnexpectedEof, invalid(&saved[..name_length + 4]));
+    /* the index saved untouched still opens */
+    fs::write(Index::index_path(&dir), &saved).unwrap();
+    assert_eq!(1, Index::open(&dir, None).unwrap().len());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
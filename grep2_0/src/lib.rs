@@ -1,95 +1,439 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::fs;
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub mod encoding;
+pub mod error;
+pub mod index;
+pub mod tui;
+pub mod watch;
+
+use encoding::Encoding;
+pub use error::GrepError;
+
+/* (line, column) of the first occurrence of the pattern -> line contents,
+in line order */
+pub type Matches = BTreeMap<(usize, usize), String>;
+
+/* matches of one file */
+pub type FileMatches = (PathBuf, Matches);
+
+/* everything a search produced */
+#[derive(Debug, Default)]
+pub struct GrepOutput {
+  pub matches: Vec<FileMatches>, /* files with at least one match */
+  pub stats: Stats,
+  pub errors: Vec<GrepError>, /* files that could not be searched and were skipped */
+}
 
+#[derive(Debug, Clone, Default)]
 pub struct Config {
   pub file_path: String,
   pub pattern: String,
   pub case_insensitive: bool,
+  pub mode: Mode,
+  pub encoding: Option<Encoding>, /* None: sniff the byte order mark, else UTF-8 */
+  pub stats: bool, /* report a Stats summary after the results */
+  pub sort: SortBy, /* order of the files when file_path is a directory */
+  pub max_count: Option<usize>, /* stop searching a file after this many matched lines */
+  pub format: Option<String>, /* template for each match, see format_match */
+  pub one_based: bool, /* print line and column numbers counting from 1 */
+}
+
+/* quickfix lines understood by vim, emacs and most editors */
+pub const VIMGREP_FORMAT: &str = "{path}:{line}:{col}:{text}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+  #[default]
+  Path,
+  Modified, /* oldest first */
+  Created,  /* oldest first */
+}
+
+/* how the pattern is applied to the file over time */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+  #[default]
+  Search, /* search the file once */
+  Follow, /* stream matches from lines appended to the file, like tail -f */
+  Watch,  /* re-search the whole file every time it changes on disk */
+  Tui,    /* browse the matches interactively, refining the pattern */
+  IndexBuild, /* build or refresh the trigram index of the directory in file_path */
+  IndexQuery, /* search the directory in file_path through its trigram index */
 }
 
 impl Config {
   pub fn new(mut args: impl Iterator<Item = String>, help: &'static str) -> Result<Config, &'static str> {
     args.next();
-    let file_path = match args.next() {
-      Some(arg) => {
-        match arg.as_str() {
-          "-h" | "--help" => return Err(help),
-          "--version" => {
-            let vers = format!("mini-grep v{}", env!("CARGO_PKG_VERSION"));
-            return Err(string_to_static_str(vers));
-          },
-          _ => arg,
+    let mut positional: Vec<String> = Vec::new();
+    let mut mode = Mode::Search;
+    let mut encoding = None;
+    let mut stats = false;
+    let mut sort = SortBy::Path;
+    let mut max_count = None;
+    let mut format = None;
+    let mut one_based = None;
+    while let Some(arg) = args.next() {
+      /* long options also accept their value as --option=value */
+      let (name, mut value) = match arg.split_once('=') {
+        Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+        _ => (arg.clone(), None),
+      };
+      let mut value_of = |option: &str| -> Result<String, &'static str> {
+        match value.take().or_else(|| args.next()) {
+          Some(value) => Ok(value),
+          None => Err(string_to_static_str(format!("{} requires a value", option))),
         }
+      };
+      match name.as_str() {
+        "-h" | "--help" => return Err(help),
+        "--version" => {
+          let vers = format!("mini-grep v{}", env!("CARGO_PKG_VERSION"));
+          return Err(string_to_static_str(vers));
+        },
+        "--follow" | "--watch" | "--tui" => {
+          let requested = match name.as_str() {
+            "--follow" => Mode::Follow,
+            "--watch" => Mode::Watch,
+            _ => Mode::Tui,
+          };
+          if mode != Mode::Search && mode != requested {
+            return Err("only one of --follow, --watch and --tui can be used");
+          }
+          mode = requested;
+        },
+        "--stats" => stats = true,
+        "--vimgrep" => format = Some(String::from(VIMGREP_FORMAT)),
+        "--format" => format = Some(value_of("--format")?),
+        "--one-based" => one_based = Some(true),
+        "--zero-based" => one_based = Some(false),
+        "--encoding" => encoding = Some(parse_encoding(&value_of("--encoding")?)?),
+        "--sort" => {
+          sort = match value_of("--sort")?.as_str() {
+            "path" => SortBy::Path,
+            "modified" => SortBy::Modified,
+            "created" => SortBy::Created,
+            _ => return Err("--sort expects path, modified or created"),
+          };
+        },
+        "-m" | "--max-count" => {
+          max_count = match value_of("--max-count")?.parse() {
+            Ok(count) => Some(count),
+            Err(_) => return Err("--max-count expects a number"),
+          };
+        },
+        _ => positional.push(arg),
+      }
+    }
+    /* `index build DIR` and `index query DIR PATTERN` subcommands. any
+    other pattern searches a file that happens to be named index */
+    let subcommand = match (positional.first(), positional.get(1)) {
+      (Some(first), Some(second)) if first == "index" => match second.as_str() {
+        "build" => Some(Mode::IndexBuild),
+        "query" => Some(Mode::IndexQuery),
+        _ => None,
       },
-      None => return Err(help),
+      _ => None,
     };
-    let pattern = match args.next() {
+    if let Some(requested) = subcommand {
+      if mode != Mode::Search {
+        return Err("--follow, --watch and --tui cannot be used with the index subcommand");
+      }
+      mode = requested;
+      positional.drain(..2);
+    }
+    let mut positional = positional.into_iter();
+    let file_path = match positional.next() {
       Some(arg) => arg,
       None => return Err(help),
     };
+    /* a directory has no end to follow, --watch searches it again instead */
+    if mode == Mode::Follow && Path::new(&file_path).is_dir() {
+      return Err("--follow expects a file, use --watch to search a directory on changes");
+    }
+    let pattern = match (mode, positional.next()) {
+      (Mode::IndexBuild, None) => String::new(), /* building takes no pattern */
+      (Mode::IndexBuild, Some(_)) => return Err("index build takes a directory and no pattern"),
+      (_, Some(arg)) => arg,
+      (_, None) => return Err(help),
+    };
     let case_insensitive: bool = match env::var("CASE_INSENSITIVE") {
       Ok(val) => val == "1",
       Err(_) => false,
     };
     Ok(Config {
-      file_path: file_path, /* inneficent solution: use clone() */ 
-      pattern: pattern, /* efficient solution: use reference with lifetime annotation */
-      case_insensitive
+      file_path, /* inneficent solution: use clone() */ 
+      pattern, /* efficient solution: use reference with lifetime annotation */
+      case_insensitive,
+      mode,
+      encoding,
+      stats,
+      sort,
+      max_count,
+      /* editors count from 1, so templated output does too unless asked otherwise */
+      one_based: one_based.unwrap_or(format.is_some()),
+      format,
     })
   }
 }
 
+fn parse_encoding(label: &str) -> Result<Encoding, &'static str> {
+  Encoding::from_label(label).ok_or_else(|| {
+    string_to_static_str(format!(
+      "unknown encoding '{}' (expected utf-8, utf-16le, utf-16be, latin1 or windows-1252)", label
+    ))
+  })
+}
+
 /* WARNING: Unsafe! Leaks String memory to mmake it static */
 fn string_to_static_str(s: String) -> &'static str {
   Box::leak(s.into_boxed_str())
 }
 
-fn search_case_sensitive(pattern: &str, contents: &str) -> HashMap<(usize, usize), String> {
-  let mut lines: HashMap<(usize, usize), String> = HashMap::new();
+/* limit stops the scan after that many matched lines */
+fn search_case_sensitive(pattern: &str, contents: &str, limit: Option<usize>) -> Matches {
+  let mut lines = Matches::new();
   contents.lines()
-    .filter(|line| line.contains(pattern))
-    .for_each(|line| {
-      let cursor_pos = contents.find(line).unwrap();
-      let line_index = contents[..cursor_pos].lines().count();
+    .enumerate() /* the position of the line, a text search could find an earlier copy */
+    .filter(|(_, line)| line.contains(pattern))
+    .take(limit.unwrap_or(usize::MAX))
+    .for_each(|(line_index, line)| {
       let column_index = line.find(pattern).unwrap();
       lines.insert( (line_index, column_index), line.to_string());
     });
   lines
 }
 
-fn search_case_insensitive(pattern: &str, contents: &str) -> HashMap<(usize, usize), String> {
-  let mut lines: HashMap<(usize, usize), String> = HashMap::new();
+fn search_case_insensitive(pattern: &str, contents: &str, limit: Option<usize>) -> Matches {
+  let mut lines = Matches::new();
+  let pattern = pattern.to_lowercase();
   contents.lines()
-    .filter(|line| {
-      line.to_lowercase()
-        .contains(pattern.to_lowercase().as_str())
+    .enumerate()
+    .filter_map(|(line_index, line)| {
+      find_lowercase(line, &pattern).map(|column_index| (line_index, column_index, line))
     })
-    .for_each(|line| {
-      let cursor_pos = contents.find(line).unwrap();
-      let line_index = contents[..cursor_pos].lines().count();
-      let column_index = line.to_lowercase()
-        .find(pattern.to_lowercase().as_str()).unwrap();
+    .take(limit.unwrap_or(usize::MAX))
+    .for_each(|(line_index, column_index, line)| {
       lines.insert( (line_index, column_index), line.to_string());
     });
   lines
 }
 
-pub fn run_mini_grep(config: Config) 
--> Result<HashMap<(usize, usize), String>, Box<dyn Error>> {
-  let contents = fs::read_to_string(config.file_path)?;
-  /* ? will return the error value from the current 
-    function for the caller to handle. 
-  */
-  let lines = if config.case_insensitive {
-    search_case_insensitive(&config.pattern, &contents)
+/* finds an already lowercased pattern in the lowercased line, returning the
+column in the original line: lowercasing can change the length of a
+character (ẞ is 3 bytes, ß is 2), so offsets of both strings differ. */
+fn find_lowercase(line: &str, lowered_pattern: &str) -> Option<usize> {
+  let lowered = line.to_lowercase();
+  let found = lowered.find(lowered_pattern)?;
+  let mut lowered_offset = 0;
+  for (column, c) in line.char_indices() {
+    lowered_offset += c.to_lowercase().map(char::len_utf8).sum::<usize>();
+    if lowered_offset > found {
+      return Some(column);
+    }
+  }
+  Some(line.len()) /* empty pattern at the end of the line */
+}
+
+/* searches contents line by line, see Matches */
+pub fn search(pattern: &str, contents: &str, case_insensitive: bool, limit: Option<usize>) -> Matches {
+  if case_insensitive {
+    search_case_insensitive(pattern, contents, limit)
   } else {
-    search_case_sensitive(&config.pattern, &contents)
+    search_case_sensitive(pattern, contents, limit)
+  }
+}
+
+/* column of the first occurrence of pattern in a single line, if any */
+pub(crate) fn match_column(pattern: &str, line: &str, case_insensitive: bool) -> Option<usize> {
+  if case_insensitive {
+    find_lowercase(line, &pattern.to_lowercase())
+  } else {
+    line.find(pattern)
+  }
+}
+
+/* fills {path}, {line}, {col} and {text} in template for a single match.
+line and col are 0-based like the search results, and shifted when
+one_based is set. unknown placeholders are kept as they are. */
+pub fn format_match(
+  template: &str,
+  path: &Path,
+  (line, col): (usize, usize),
+  text: &str,
+  one_based: bool,
+) -> String {
+  let offset = if one_based { 1 } else { 0 };
+  let mut formatted = String::with_capacity(template.len() + text.len());
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    formatted.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let end = match rest.find('}') {
+      Some(end) => end,
+      None => break,
+    };
+    match &rest[1..end] {
+      "path" => formatted.push_str(&path.display().to_string()),
+      "line" => formatted.push_str(&(line + offset).to_string()),
+      "col" => formatted.push_str(&(col + offset).to_string()),
+      "text" => formatted.push_str(text),
+      _ => formatted.push_str(&rest[..=end]),
+    }
+    rest = &rest[end + 1..];
+  }
+  formatted.push_str(rest);
+  formatted
+}
+
+/* number of non overlapping occurrences of pattern in a single line */
+fn count_matches(pattern: &str, line: &str, case_insensitive: bool) -> usize {
+  if case_insensitive {
+    line.to_lowercase().matches(pattern.to_lowercase().as_str()).count()
+  } else {
+    line.matches(pattern).count()
+  }
+}
+
+/* how much work a search did */
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+  pub files_searched: usize,
+  pub bytes_read: u64,      /* raw file bytes, before decoding */
+  pub lines_scanned: usize,
+  pub matched_lines: usize,
+  pub total_matches: usize, /* every occurrence, several per line possible */
+  pub elapsed: Duration,
+}
+
+impl fmt::Display for Stats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "{} matches", self.total_matches)?;
+    writeln!(f, "{} matched lines", self.matched_lines)?;
+    writeln!(f, "{} files searched", self.files_searched)?;
+    writeln!(f, "{} bytes read", self.bytes_read)?;
+    writeln!(f, "{} lines scanned", self.lines_scanned)?;
+    write!(f, "{:.6} seconds elapsed", self.elapsed.as_secs_f64())
+  }
+}
+
+/* reads, decodes and searches a single file, adding its numbers to stats.
+with skip_binary, undecodable files holding NUL bytes are left out
+silently (no matches), as grep does for binary files. */
+pub(crate) fn search_file(path: &Path, config: &Config, stats: &mut Stats, skip_binary: bool)
+-> Result<Matches, GrepError> {
+  let bytes = fs::read(path).map_err(|e| GrepError::io(path, e))?;
+  let contents = match encoding::decode(&bytes, config.encoding) {
+    Ok(contents) => contents,
+    Err(_) if skip_binary && bytes.contains(&0) => return Ok(Matches::new()),
+    Err(source) => return Err(GrepError::Encoding { path: path.to_path_buf(), source }),
   };
+  let lines = search(&config.pattern, &contents, config.case_insensitive, config.max_count);
+  stats.files_searched += 1;
+  stats.bytes_read += bytes.len() as u64;
+  /* a search cut short by max_count did not scan past its last match */
+  stats.lines_scanned += match (config.max_count, lines.keys().next_back()) {
+    (Some(limit), Some(&(last_line, _))) if lines.len() == limit => last_line + 1,
+    _ => contents.lines().count(),
+  };
+  stats.matched_lines += lines.len();
+  stats.total_matches += lines.values()
+    .map(|line| count_matches(&config.pattern, line, config.case_insensitive))
+    .sum::<usize>();
   Ok(lines)
 }
 
+/* regular files below dir, skipping hidden entries. only an unreadable dir
+itself is fatal, entries below it that cannot be read end up in errors. */
+pub(crate) fn walk(dir: &Path, files: &mut Vec<PathBuf>, errors: &mut Vec<GrepError>)
+-> Result<(), GrepError> {
+  let entries = fs::read_dir(dir).map_err(|e| GrepError::io(dir, e))?;
+  for entry in entries {
+    let entry = match entry {
+      Ok(entry) => entry,
+      Err(e) => {
+        errors.push(GrepError::io(dir, e));
+        continue;
+      },
+    };
+    if entry.file_name().to_string_lossy().starts_with('.') {
+      continue;
+    }
+    let path = entry.path();
+    match entry.file_type() {
+      Ok(file_type) if file_type.is_dir() => {
+        if let Err(e) = walk(&path, files, errors) {
+          errors.push(e);
+        }
+      },
+      Ok(file_type) if file_type.is_file() => files.push(path),
+      Ok(_) => {}, /* symlinks, sockets... */
+      Err(e) => errors.push(GrepError::io(&path, e)),
+    }
+  }
+  Ok(())
+}
+
+/* orders files for the results. timestamps the platform or file system
+does not record are reported as an error instead of being guessed. */
+pub(crate) fn sort_files(files: &mut [PathBuf], sort: SortBy) -> Result<(), GrepError> {
+  let timestamp = |path: &Path| -> Result<SystemTime, GrepError> {
+    let metadata = fs::metadata(path).map_err(|e| GrepError::io(path, e))?;
+    let time = match sort {
+      SortBy::Created => metadata.created(),
+      _ => metadata.modified(),
+    };
+    time.map_err(|e| GrepError::io(path, e))
+  };
+  match sort {
+    SortBy::Path => files.sort(),
+    SortBy::Modified | SortBy::Created => {
+      let mut keyed = files.iter()
+        .map(|path| Ok((timestamp(path)?, path.clone())))
+        .collect::<Result<Vec<(SystemTime, PathBuf)>, GrepError>>()?;
+      keyed.sort();
+      for (slot, (_, path)) in files.iter_mut().zip(keyed) {
+        *slot = path;
+      }
+    },
+  }
+  Ok(())
+}
+
+/* searches file_path, or every file below it when it is a directory.
+files come back in config.sort order, with their matches in line order.
+an invalid pattern or an unsearchable file_path fail the whole search, files
+below a directory that cannot be searched are skipped and listed in errors. */
+pub fn run_mini_grep(config: &Config) -> Result<GrepOutput, GrepError> {
+  let started = Instant::now();
+  error::validate_pattern(&config.pattern)?;
+  let mut output = GrepOutput::default();
+  let root = Path::new(&config.file_path);
+  if root.is_dir() {
+    let mut files = Vec::new();
+    walk(root, &mut files, &mut output.errors)?;
+    sort_files(&mut files, config.sort)?;
+    for path in files {
+      match search_file(&path, config, &mut output.stats, true) {
+        Ok(lines) if lines.is_empty() => {},
+        Ok(lines) => output.matches.push((path, lines)),
+        Err(e) => output.errors.push(e),
+      }
+    }
+  } else {
+    let lines = search_file(root, config, &mut output.stats, false)?;
+    if !lines.is_empty() {
+      output.matches.push((root.to_path_buf(), lines));
+    }
+  }
+  output.stats.elapsed = started.elapsed();
+  Ok(output)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -100,7 +444,7 @@ mod tests {
 Rust:
 safe, fast, productive.
 Pick three.";
-    let found = search_case_sensitive(query, contents);
+    let found = search_case_sensitive(query, contents, None);
     let found_contents = found.values().cloned().collect::<Vec<String>>();
     assert_eq!(
       vec!["safe, fast, productive."], 
@@ -115,7 +459,7 @@ Pick three.";
 Rust:
 safe, fast, productive.
 Pick three.";
-    let found = search_case_sensitive(query, contents);
+    let found = search_case_sensitive(query, contents, None);
     let found_line_cols = found.keys().cloned().collect::<Vec<(usize,usize)>>();
     assert_eq!(
       vec![(1, 15)], 
@@ -124,6 +468,7 @@ Pick three.";
   }
 
   #[test]
+  #[allow(clippy::unnecessary_sort_by)]
   fn search_case_insensitive_result_content() {
     let query = "rUsT";
     let contents = "\
@@ -131,7 +476,7 @@ Rust:
 safe, fast, productive.
 Trust me.
 Pick three.";
-    let found = search_case_insensitive(query, contents);
+    let found = search_case_insensitive(query, contents, None);
     let mut found_contents = found.values().cloned().collect::<Vec<String>>();
     /* sort results by string length to get a deterministic answer */
     found_contents.sort_by(|a, b| a.len().cmp(&b.len()));
@@ -149,7 +494,7 @@ Rust:
 safe, fast, productive.
 Trust me.
 Pick three.";
-    let found = search_case_insensitive(query, contents);
+    let found = search_case_insensitive(query, contents, None);
     let mut found_line_cols = found.keys().cloned().collect::<Vec<(usize, usize)>>();
     /* sort by line, column pair to get a determinisic behaviour*/
     found_line_cols.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
@@ -158,4 +503,210 @@ Pick three.";
       found_line_cols
     );
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn search_case_sensitive_stops_at_limit() {
+    let query = "t";
+    let contents = "\
+Rust:
+safe, fast, productive.
+Trust me.
+Pick three.";
+    let found = search_case_sensitive(query, contents, Some(2));
+    let found_line_cols = found.keys().cloned().collect::<Vec<(usize, usize)>>();
+    assert_eq!(
+      vec![(0, 3), (1, 9)],
+      found_line_cols
+    );
+  }
+
+  fn config(args: &[&str]) -> Result<Config, &'static str> {
+    let args = ["grep"].iter().chain(args).map(|arg| arg.to_string()).collect::<Vec<_>>();
+    Config::new(args.into_iter(), "help")
+  }
+
+  #[test]
+  fn index_subcommands_only_follow_index_build_or_query() {
+    let build = config(&["index", "build", "src"]).unwrap();
+    assert_eq!((Mode::IndexBuild, "src", ""), (build.mode, build.file_path.as_str(), build.pattern.as_str()));
+    let query = config(&["index", "query", "src", "fn"]).unwrap();
+    assert_eq!((Mode::IndexQuery, "src", "fn"), (query.mode, query.file_path.as_str(), query.pattern.as_str()));
+    /* a search of a file named index */
+    let search = config(&["index", "todo"]).unwrap();
+    assert_eq!((Mode::Search, "index", "todo"), (search.mode, search.file_path.as_str(), search.pattern.as_str()));
+    assert!(config(&["index", "build", "src", "fn"]).is_err());
+    assert_eq!(Err("help"), config(&["index", "query", "src"]).map(|config| config.mode));
+  }
+
+  #[test]
+  fn follow_rejects_directories() {
+    assert!(config(&["--follow", "src", "fn"]).is_err());
+    assert_eq!(Ok(Mode::Follow), config(&["--follow", "Cargo.toml", "name"]).map(|config| config.mode));
+    assert_eq!(Ok(Mode::Watch), config(&["--watch", "src", "fn"]).map(|config| config.mode));
+  }
+
+  #[test]
+  fn run_mini_grep_skips_unsearchable_files_and_continues() {
+    let dir = std::env::temp_dir().join(format!("mini-grep-{}-errors", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "Rust:\nTrust me.").unwrap();
+    fs::write(dir.join("b.bin"), [0x7F, b'E', b'L', b'F', 0, 0xFF]).unwrap();
+    fs::write(dir.join("c.txt"), [b'r', b'u', b's', b't', 0xE9]).unwrap();
+    fs::write(dir.join("d.txt"), "rust").unwrap();
+    let config = Config {
+      file_path: dir.to_string_lossy().into_owned(),
+      pattern: String::from("rust"),
+      ..Config::default()
+    };
+    let output = run_mini_grep(&config).unwrap();
+    let paths = output.matches.iter().map(|(path, _)| path.clone()).collect::<Vec<PathBuf>>();
+    assert_eq!(vec![dir.join("a.txt"), dir.join("d.txt")], paths);
+    assert_eq!(1, output.errors.len());
+    assert!(matches!(&output.errors[0], GrepError::Encoding { path, .. } if path == &dir.join("c.txt")));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn run_mini_grep_rejects_multi_line_patterns() {
+    let config = Config {
+      file_path: String::from("resources/poem.txt"),
+      pattern: String::from("nobody\nWho"),
+      ..Config::default()
+    };
+    match run_mini_grep(&config) {
+      Err(GrepError::InvalidPattern { position, .. }) => assert_eq!(6, position),
+      other => panic!("expected an invalid pattern, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn format_match_fills_placeholders() {
+    let path = Path::new("src/lib.rs");
+    assert_eq!(
+      "src/lib.rs:2:16:safe, fast, productive.",
+      format_match(VIMGREP_FORMAT, path, (1, 15), "safe, fast, productive.", true)
+    );
+    assert_eq!(
+      "1/15 {unknown} {text",
+      format_match("{line}/{col} {unknown} {text", path, (1, 15), "ignored", false)
+    );
+  }
+
+  #[test]
+  fn run_mini_grep_collects_stats() {
+    let path = std::env::temp_dir().join(format!("mini-grep-{}-stats", std::process::id()));
+    fs::write(&path, "Rust:\nsafe, fast, productive.\nTrust rust.\n").unwrap();
+    let config = Config {
+      file_path: path.to_string_lossy().into_owned(),
+      pattern: String::from("rust"),
+      case_insensitive: true,
+      ..Config::default()
+    };
+    let GrepOutput { matches: found, stats, .. } = run_mini_grep(&config).unwrap();
+    assert_eq!(2, found[0].1.len());
+    assert_eq!(1, stats.files_searched);
+    assert_eq!(42, stats.bytes_read);
+    assert_eq!(3, stats.lines_scanned);
+    assert_eq!(2, stats.matched_lines);
+    assert_eq!(3, stats.total_matches);
+    fs::remove_file(&path).unwrap();
+  }
+}
+
+/* invariants of the searchers, checked on generated inputs */
+#[cfg(test)]
+mod properties {
+  use super::*;
+  use proptest::prelude::*;
+
+  /* a small alphabet so that patterns actually occur, with characters
+  whose lowercase form has a different UTF-8 length (ẞ -> ß) and
+  windows line endings. the final sigma is left out: its lowercase
+  depends on the surrounding letters, which no column can account for. */
+  const ALPHABET: &[char] = &['a', 'b', 'A', 'B', ' ', 'é', 'É', 'ß', 'ẞ', '\r', '\n'];
+
+  fn contents() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(ALPHABET), 0..200)
+      .prop_map(|chars| chars.into_iter().collect())
+  }
+
+  fn pattern() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop::sample::select(&ALPHABET[..9]), 1..4)
+      .prop_map(|chars| chars.into_iter().collect())
+  }
+
+  /* str::lines, written out: a line ends at \n, or \r\n, or the end of
+  the input when it is not empty */
+  fn reference_lines(contents: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut rest = contents;
+    while let Some(end) = rest.find('\n') {
+      let line = &rest[..end];
+      lines.push(line.strip_suffix('\r').unwrap_or(line));
+      rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+      lines.push(rest);
+    }
+    lines
+  }
+
+  fn reference_search(pattern: &str, contents: &str, case_insensitive: bool) -> Vec<usize> {
+    reference_lines(contents).into_iter()
+      .enumerate()
+      .filter(|(_, line)| match case_insensitive {
+        true => line.to_lowercase().contains(&pattern.to_lowercase()),
+        false => line.contains(pattern),
+      })
+      .map(|(index, _)| index)
+      .collect()
+  }
+
+  fn line_indices(found: &Matches) -> Vec<usize> {
+    found.keys().map(|&(line, _)| line).collect()
+  }
+
+  proptest! {
+    #[test]
+    fn line_numbers_match_reference(contents in contents(), pattern in pattern()) {
+      let found = search_case_sensitive(&pattern, &contents, None);
+      prop_assert_eq!(reference_search(&pattern, &contents, false), line_indices(&found));
+      let found = search_case_insensitive(&pattern, &contents, None);
+      prop_assert_eq!(reference_search(&pattern, &contents, true), line_indices(&found));
+    }
+
+    #[test]
+    fn reported_columns_start_the_pattern(contents in contents(), pattern in pattern()) {
+      let lines = reference_lines(&contents);
+      for ((line, column), text) in search_case_sensitive(&pattern, &contents, None) {
+        prop_assert_eq!(lines[line], text.as_str());
+        prop_assert_eq!(Some(column), text.find(pattern.as_str()));
+      }
+      let lowered = pattern.to_lowercase();
+      for ((line, column), text) in search_case_insensitive(&pattern, &contents, None) {
+        prop_assert_eq!(lines[line], text.as_str());
+        prop_assert!(text.is_char_boundary(column));
+        prop_assert!(text[column..].to_lowercase().starts_with(&lowered));
+      }
+    }
+
+    #[test]
+    fn case_insensitive_is_a_superset(contents in contents(), pattern in pattern()) {
+      let insensitive = line_indices(&search_case_insensitive(&pattern, &contents, None));
+      for line in line_indices(&search_case_sensitive(&pattern, &contents, None)) {
+        prop_assert!(insensitive.contains(&line));
+      }
+    }
+
+    #[test]
+    fn limit_keeps_the_first_matches(contents in contents(), pattern in pattern(), limit in 0..5usize) {
+      for case_insensitive in [false, true] {
+        let all = search(&pattern, &contents, case_insensitive, None);
+        let limited = search(&pattern, &contents, case_insensitive, Some(limit));
+        let expected: Matches = all.into_iter().take(limit).collect();
+        prop_assert_eq!(expected, limited);
+      }
+    }
+  }
+}
+
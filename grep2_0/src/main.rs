@@ -1,16 +1,44 @@
 use std::env;
+use std::io;
+use std::path::Path;
 use std::process;
 
-use grep::Config; /* import local module */
+use grep::{Config, FileMatches, GrepOutput, Mode, Stats}; /* import local module */
+use grep::watch::{Follower, Watcher};
 
+#[allow(clippy::redundant_static_lifetimes)]
 const HELP: &'static str = "
-grep finds a string pattern in a file.
+grep finds a string pattern in a file, or in every file below a directory.
 Usage: 
-  grep <FILEPATH> <PATTERN>
+  grep [OPTIONS] <FILEPATH|DIR> <PATTERN>
+  grep index build <DIR>
+  grep index query <DIR> <PATTERN>
 
 Options:
   -h, --help      print this help menu
   --version       print version
+  --follow        keep printing matches as lines are appended to the file (tail -f)
+  --watch         search the file again every time it changes on disk
+  --tui           browse the matches with a preview, refine the pattern as you
+                  type and open the selected line in $EDITOR with enter
+  --stats         print how much was searched, and how long it took
+                  (not with --follow)
+  --encoding ENC  decode inputs as utf-8, utf-16le, utf-16be, latin1 or windows-1252
+                  (default: detect a byte order mark, else utf-8). columns are
+                  reported in the decoded UTF-8 text
+  --sort KEY      order files by path (default), modified or created time
+  -m, --max-count N
+                  stop searching a file after N matching lines
+  --vimgrep       print path:line:col:text quickfix lines for editors
+  --format TMPL   print each match with a template using {path}, {line},
+                  {col} and {text}, e.g. '{path}:{line}:{col}:{text}'
+  --one-based     count lines and columns from 1 (default with --vimgrep
+                  and --format)
+  --zero-based    count lines and columns from 0 (default otherwise)
+
+Subcommands:
+  index build     write (or refresh) a trigram index of DIR to DIR/.mini-grep-index
+  index query     search DIR through its index, refreshing files changed since
 ";
 
 fn main() {
@@ -22,19 +50,163 @@ fn main() {
       process::exit(1); /* exit with error code 1 */
     },
   };
-  let contents_result = grep::run_mini_grep(config).unwrap_or_else( |err| {
+  match config.mode {
+    Mode::Search => {
+      let output = search(&config);
+      report(&config, output);
+    },
+    Mode::Follow => follow(&config),
+    Mode::Watch => watch(&config),
+    Mode::Tui => grep::tui::run(&config).unwrap_or_else( |err| {
+      eprintln!("mini-grep error: {}", err);
+      process::exit(1);
+    }),
+    Mode::IndexBuild => build_index(&config),
+    Mode::IndexQuery => query_index(&config),
+  }
+}
+
+fn search(config: &Config) -> GrepOutput {
+  grep::run_mini_grep(config).unwrap_or_else( |err| {
     eprintln!("mini-grep error: {}", err);
     process::exit(1);
-  });
+  })
+}
+
+/* the path column is only shown when more than one file could match */
+fn with_path(config: &Config) -> bool {
+  config.mode == Mode::IndexQuery || Path::new(&config.file_path).is_dir()
+}
+
+/* the table header, unless matches are printed with a template */
+fn print_header(config: &Config) {
+  match (&config.format, with_path(config)) {
+    (Some(_), _) => {},
+    (None, true) => println!("Path\tLine/Column\tContent"),
+    (None, false) => println!("Line/Column\tContent"),
+  }
+}
+
+fn print_match(config: &Config, path: &Path, line_column_tuple: (usize, usize), line: &str) {
+  if let Some(template) = &config.format {
+    println!("{}", grep::format_match(template, path, line_column_tuple, line, config.one_based));
+    return;
+  }
+  let offset = if config.one_based { 1 } else { 0 };
+  let line_number = line_column_tuple.0 + offset;
+  let column_number = line_column_tuple.1 + offset;
+  if with_path(config) {
+    print!("{}\t", path.display());
+  }
+  println!("l{}/c{}\t{}", line_number, column_number, line);
+}
+
+fn print_matches(config: &Config, contents_result: Vec<FileMatches>) {
   match contents_result.len() {
     0 => eprintln!("No matches found."),
     _ => {
-      println!("Line/Column\tContent");
-      for (line_column_tuple, line) in contents_result {
-        let line_number = line_column_tuple.0;
-        let column_number = line_column_tuple.1;
-        println!("l{}/c{}\t{}", line_number, column_number, line);
+      print_header(config);
+      for (path, lines) in contents_result {
+        for (line_column_tuple, line) in lines {
+          print_match(config, &path, line_column_tuple, &line);
+        }
       }
     },
   }
 }
+
+fn print_stats(config: &Config, stats: &Stats) {
+  if config.stats {
+    println!("\n{}", stats);
+  }
+}
+
+/* prints a finished search, skipped files last. true if none was skipped. */
+fn print_output(config: &Config, output: GrepOutput) -> bool {
+  print_matches(config, output.matches);
+  print_stats(config, &output.stats);
+  for err in &output.errors {
+    eprintln!("mini-grep: skipped {}", err);
+  }
+  output.errors.is_empty()
+}
+
+/* a search that had to skip files still fails, once everything is printed */
+fn report(config: &Config, output: GrepOutput) {
+  if !print_output(config, output) {
+    process::exit(1);
+  }
+}
+
+fn wait_for_change(watcher: &mut Watcher) {
+  watcher.wait().unwrap_or_else( |err| {
+    eprintln!("mini-grep error: {}", err);
+    process::exit(1);
+  });
+}
+
+fn follow(config: &Config) {
+  let mut watcher = Watcher::new(&config.file_path);
+  let mut follower = Follower::new(config);
+  let path = Path::new(&config.file_path);
+  print_header(config);
+  let mut missing = false;
+  loop {
+    match follower.poll() {
+      Ok(matches) => {
+        missing = false;
+        for (line_column_tuple, line) in matches {
+          print_match(config, path, line_column_tuple, &line);
+        }
+      },
+      /* the file may be briefly missing while it is rotated, said once */
+      Err(err) if err.downcast_ref::<io::Error>().is_some_and(|err| err.kind() == io::ErrorKind::NotFound) => {
+        if !missing {
+          eprintln!("mini-grep: {} is missing, waiting for it", config.file_path);
+          missing = true;
+        }
+      },
+      Err(err) => {
+        eprintln!("mini-grep error: {}", err);
+        process::exit(1);
+      },
+    }
+    wait_for_change(&mut watcher);
+  }
+}
+
+fn watch(config: &Config) {
+  let mut watcher = Watcher::new(&config.file_path);
+  if watcher.is_polling() {
+    eprintln!("mini-grep: file notifications unavailable, polling {}", config.file_path);
+  }
+  loop {
+    match grep::run_mini_grep(config) {
+      Ok(output) => {
+        print_output(config, output);
+      },
+      Err(err) => eprintln!("mini-grep error: {}", err),
+    }
+    wait_for_change(&mut watcher);
+    println!("\n{} changed, searching again.", config.file_path);
+  }
+}
+
+fn build_index(config: &Config) {
+  let summary = grep::index::build_index(config).unwrap_or_else( |err| {
+    eprintln!("mini-grep error: {}", err);
+    process::exit(1);
+  });
+  println!(
+    "Indexed {} files ({} unchanged, {} removed, {} skipped).",
+    summary.indexed, summary.unchanged, summary.removed, summary.skipped
+  );
+}
+
+fn query_index(config: &Config) {
+  let output = grep::index::query_index(config).unwrap_or_else( |err| {
+    eprintln!("mini-grep error: {}", err);
+    process::exit(1);
+  });
+  report(config, output);
+}
@@ -0,0 +1,386 @@
+//! interactive result browser for the --tui mode.
+//! the upper pane lists every match, the lower one previews the lines
+//! around the selected match. typing refines the pattern and searches
+//! again, enter opens the selected file:line in $EDITOR.
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crossterm::{
+  cursor,
+  event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+  queue,
+  style::{self, Attribute, Color},
+  terminal::{self, ClearType},
+};
+
+use crate::{encoding, run_mini_grep, Config};
+
+/* lines shown above and below the match in the preview pane */
+const PREVIEW_CONTEXT: usize = 5;
+
+/* one row of the result list */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hit {
+  pub path: PathBuf,
+  pub line: usize,   /* 0-based, as in the search results */
+  pub column: usize,
+  pub text: String,
+}
+
+/* what the browser shows, independent of the terminal */
+pub struct Browser {
+  config: Config,
+  hits: Vec<Hit>,
+  selected: usize,
+  scroll: usize, /* index of the first hit visible in the list */
+  status: String,
+  previews: HashMap<PathBuf, Vec<String>>, /* decoded lines of the files previewed so far */
+}
+
+impl Browser {
+  pub fn new(config: &Config) -> Browser {
+    let mut browser = Browser {
+      config: config.clone(),
+      hits: Vec::new(),
+      selected: 0,
+      scroll: 0,
+      status: String::new(),
+      previews: HashMap::new(),
+    };
+    browser.search();
+    browser
+  }
+
+  pub fn pattern(&self) -> &str {
+    &self.config.pattern
+  }
+
+  pub fn hits(&self) -> &[Hit] {
+    &self.hits
+  }
+
+  pub fn selected(&self) -> Option<&Hit> {
+    self.hits.get(self.selected)
+  }
+
+  /* runs the search again for the current pattern, selecting the first hit */
+  fn search(&mut self) {
+    self.hits.clear();
+    self.selected = 0;
+    self.scroll = 0;
+    if self.config.pattern.is_empty() {
+      self.status = String::from("type a pattern");
+      return;
+    }
+    match run_mini_grep(&self.config) {
+      Ok(output) => {
+        for (path, lines) in output.matches {
+          for ((line, column), text) in lines {
+            self.hits.push(Hit { path: path.clone(), line, column, text });
+          }
+        }
+        self.status = format!(
+          "{} matched lines in {} files", output.stats.matched_lines, output.stats.files_searched
+        );
+        if !output.errors.is_empty() {
+          self.status.push_str(&format!(", {} files skipped", output.errors.len()));
+        }
+      },
+      Err(err) => self.status = format!("error: {}", err),
+    }
+  }
+
+  pub fn push_char(&mut self, c: char) {
+    self.config.pattern.push(c);
+    self.search();
+  }
+
+  pub fn pop_char(&mut self) {
+    if self.config.pattern.pop().is_some() {
+      self.search();
+    }
+  }
+
+  /* moves the selection by delta rows, clamped to the list */
+  pub fn move_selection(&mut self, delta: isize) {
+    if self.hits.is_empty() {
+      return;
+    }
+    let last = self.hits.len() - 1;
+    self.selected = self.selected.saturating_add_signed(delta).min(last);
+  }
+
+  /* keeps the selected hit inside a list pane of height rows */
+  fn scroll_into_view(&mut self, height: usize) {
+    if self.selected < self.scroll {
+      self.scroll = self.selected;
+    } else if height > 0 && self.selected >= self.scroll + height {
+      self.scroll = self.selected + 1 - height;
+    }
+  }
+
+  /* lines and columns are shown counted as in the other outputs */
+  fn offset(&self) -> usize {
+    if self.config.one_based { 1 } else { 0 }
+  }
+
+  /* a row of the result list */
+  fn label(&self, hit: &Hit) -> String {
+    let offset = self.offset();
+    format!("{}:{}:{}  {}", hit.path.display(), hit.line + offset, hit.column + offset, hit.text)
+  }
+
+  /* a row of the preview, index being the line index in the file */
+  fn numbered(&self, index: usize, text: &str) -> String {
+    format!("{:>6}  {}", index + self.offset(), text)
+  }
+
+  /* (line index, text) around the selected hit */
+  fn preview(&mut self) -> Vec<(usize, String)> {
+    let hit = match self.hits.get(self.selected) {
+      Some(hit) => hit.clone(),
+      None => return Vec::new(),
+    };
+    let encoding = self.config.encoding;
+    let lines = self.previews.entry(hit.path.clone()).or_insert_with(|| {
+      match encoding::read_to_string(&hit.path, encoding) {
+        Ok(contents) => contents.lines().map(String::from).collect(),
+        Err(_) => Vec::new(),
+      }
+    });
+    let first = hit.line.saturating_sub(PREVIEW_CONTEXT);
+    lines.iter()
+      .enumerate()
+      .skip(first)
+      .take(2 * PREVIEW_CONTEXT + 1)
+      .map(|(index, text)| (index, text.clone()))
+      .collect()
+  }
+}
+
+/* the command opening path at the 1-based line in editor */
+pub fn editor_command(editor: &str, path: &Path, line: usize) -> Command {
+  /* $EDITOR may carry arguments, e.g. "code --wait" */
+  let mut words = editor.split_whitespace();
+  let mut command = Command::new(words.next().unwrap_or("vi"));
+  command.args(words);
+  let program = Path::new(command.get_program())
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_default();
+  match program.as_str() {
+    "code" | "code-insiders" | "subl" => {
+      command.arg("--goto").arg(format!("{}:{}", path.display(), line))
+    },
+    _ => command.arg(format!("+{}", line)).arg(path),
+  };
+  command
+}
+
+/* runs the browser until the user quits */
+pub fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+  let mut browser = Browser::new(config);
+  let mut stdout = io::stdout();
+  enter(&mut stdout)?;
+  let result = event_loop(&mut browser, &mut stdout);
+  leave(&mut stdout)?;
+  result
+}
+
+fn enter(stdout: &mut io::Stdout) -> io::Result<()> {
+  terminal::enable_raw_mode()?;
+  queue!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+  stdout.flush()
+}
+
+fn leave(stdout: &mut io::Stdout) -> io::Result<()> {
+  queue!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+  stdout.flush()?;
+  terminal::disable_raw_mode()
+}
+
+fn event_loop(browser: &mut Browser, stdout: &mut io::Stdout) -> Result<(), Box<dyn Error>> {
+  loop {
+    draw(browser, stdout)?;
+    let key = match event::read()? {
+      Event::Key(key) if key.kind != KeyEventKind::Release => key,
+      _ => continue, /* resizes are picked up by the next draw */
+    };
+    let list_height = list_height(terminal::size()?.1 as usize) as isize;
+    match key {
+      KeyEvent { code: KeyCode::Esc, .. } => return Ok(()),
+      KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. } => return Ok(()),
+      KeyEvent { code: KeyCode::Char('p'), modifiers: KeyModifiers::CONTROL, .. }
+      | KeyEvent { code: KeyCode::Up, .. } => browser.move_selection(-1),
+      KeyEvent { code: KeyCode::Char('n'), modifiers: KeyModifiers::CONTROL, .. }
+      | KeyEvent { code: KeyCode::Down, .. } => browser.move_selection(1),
+      KeyEvent { code: KeyCode::PageUp, .. } => browser.move_selection(-list_height),
+      KeyEvent { code: KeyCode::PageDown, .. } => browser.move_selection(list_height),
+      KeyEvent { code: KeyCode::Home, .. } => browser.move_selection(isize::MIN),
+      KeyEvent { code: KeyCode::End, .. } => browser.move_selection(isize::MAX),
+      KeyEvent { code: KeyCode::Backspace, .. } => browser.pop_char(),
+      KeyEvent { code: KeyCode::Enter, .. } => open_selected(browser, stdout)?,
+      KeyEvent { code: KeyCode::Char(c), modifiers, .. }
+        if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => browser.push_char(c),
+      _ => {},
+    }
+  }
+}
+
+/* hands the terminal over to $EDITOR, then takes it back */
+fn open_selected(browser: &mut Browser, stdout: &mut io::Stdout) -> Result<(), Box<dyn Error>> {
+  let hit = match browser.selected() {
+    Some(hit) => hit.clone(),
+    None => return Ok(()),
+  };
+  let editor = std::env::var("VISUAL")
+    .or_else(|_| std::env::var("EDITOR"))
+    .unwrap_or_else(|_| String::from("vi"));
+  leave(stdout)?;
+  let status = editor_command(&editor, &hit.path, hit.line + 1).status();
+  enter(stdout)?;
+  match status {
+    Ok(status) if status.success() => {},
+    Ok(status) => browser.status = format!("{} exited with {}", editor, status),
+    Err(err) => browser.status = format!("could not run {}: {}", editor, err),
+  }
+  /* the file may have been edited: drop its cached preview */
+  browser.previews.remove(&hit.path);
+  Ok(())
+}
+
+/* rows for the list: half of what is left after the prompt, separator and status */
+fn list_height(rows: usize) -> usize {
+  rows.saturating_sub(3) / 2
+}
+
+/* cuts text to width characters, expanding tabs so columns line up */
+fn fit(text: &str, width: usize) -> String {
+  text.replace('\t', "    ").chars().take(width).collect()
+}
+
+fn draw(browser: &mut Browser, stdout: &mut io::Stdout) -> io::Result<()> {
+  let (columns, rows) = terminal::size()?;
+  let (width, rows) = (columns as usize, rows as usize);
+  let list_rows = list_height(rows);
+  let preview_rows = rows.saturating_sub(3 + list_rows);
+  browser.scroll_into_view(list_rows);
+  queue!(stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+
+  queue!(
+    stdout,
+    style::SetAttribute(Attribute::Bold),
+    style::Print("pattern> "),
+    style::SetAttribute(Attribute::Reset),
+    style::Print(fit(browser.pattern(), width.saturating_sub(9))),
+  )?;
+
+  let hits = browser.hits();
+  for row in 0..list_rows {
+    let index = browser.scroll + row;
+    let hit = match hits.get(index) {
+      Some(hit) => hit,
+      None => break,
+    };
+    let label = browser.label(hit);
+    queue!(stdout, cursor::MoveTo(0, 1 + row as u16))?;
+    if index == browser.selected {
+      queue!(stdout, style::SetAttribute(Attribute::Reverse))?;
+    }
+    queue!(stdout, style::Print(fit(&label, width)), style::SetAttribute(Attribute::Reset))?;
+  }
+
+  let separator_row = 1 + list_rows as u16;
+  queue!(
+    stdout,
+    cursor::MoveTo(0, separator_row),
+    style::SetForegroundColor(Color::DarkGrey),
+    style::Print("─".repeat(width)),
+    style::ResetColor,
+  )?;
+
+  let selected_line = browser.selected().map(|hit| hit.line);
+  for (row, (index, text)) in browser.preview().into_iter().take(preview_rows).enumerate() {
+    queue!(stdout, cursor::MoveTo(0, separator_row + 1 + row as u16))?;
+    let numbered = browser.numbered(index, &text);
+    if Some(index) == selected_line {
+      queue!(stdout, style::SetForegroundColor(Color::Yellow))?;
+    }
+    queue!(stdout, style::Print(fit(&numbered, width)), style::ResetColor)?;
+  }
+
+  let help = "up/down move  enter open in $EDITOR  esc quit";
+  queue!(
+    stdout,
+    cursor::MoveTo(0, rows.saturating_sub(1) as u16),
+    style::SetAttribute(Attribute::Dim),
+    style::Print(fit(&format!("{}  |  {}", browser.status, help), width)),
+    style::SetAttribute(Attribute::Reset),
+  )?;
+  stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn editor_command_jumps_to_line() {
+    let command = editor_command("vim", Path::new("src/lib.rs"), 12);
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!(vec!["+12", "src/lib.rs"], args);
+
+    let command = editor_command("code --wait", Path::new("src/lib.rs"), 3);
+    let args: Vec<_> = command.get_args().collect();
+    assert_eq!("code", command.get_program());
+    assert_eq!(vec!["--wait", "--goto", "src/lib.rs:3"], args);
+  }
+
+  #[test]
+  fn browser_refines_pattern_and_clamps_selection() {
+    let path = std::env::temp_dir().join(format!("mini-grep-{}-tui", std::process::id()));
+    std::fs::write(&path, "Rust:\nsafe, fast, productive.\nTrust me.\n").unwrap();
+    let config = Config {
+      file_path: path.to_string_lossy().into_owned(),
+      pattern: String::from("ust"),
+      ..Config::default()
+    };
+    let mut browser = Browser::new(&config);
+    assert_eq!(2, browser.hits().len());
+    browser.move_selection(5);
+    assert_eq!(2, browser.selected().unwrap().line);
+    browser.move_selection(isize::MIN);
+    assert_eq!(0, browser.selected().unwrap().line);
+
+    browser.push_char(' ');
+    assert_eq!(1, browser.hits().len());
+    assert_eq!("Trust me.", browser.selected().unwrap().text);
+    assert_eq!(3, browser.preview().len());
+    browser.pop_char();
+    assert_eq!(2, browser.hits().len());
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn preview_numbers_lines_like_the_results() {
+    let path = std::env::temp_dir().join(format!("mini-grep-{}-tui-numbers", std::process::id()));
+    std::fs::write(&path, "Rust:\nsafe, fast, productive.\nTrust me.\n").unwrap();
+    for (one_based, label, first_row) in [(false, ":0:1  Rust:", "     0  Rust:"), (true, ":1:2  Rust:", "     1  Rust:")] {
+      let config = Config {
+        file_path: path.to_string_lossy().into_owned(),
+        pattern: String::from("ust"),
+        one_based,
+        ..Config::default()
+      };
+      let mut browser = Browser::new(&config);
+      let hit = browser.selected().unwrap().clone();
+      assert!(browser.label(&hit).ends_with(label));
+      let (index, text) = browser.preview().remove(0);
+      assert_eq!(first_row, browser.numbered(index, &text));
+    }
+    std::fs::remove_file(&path).unwrap();
+  }
+}
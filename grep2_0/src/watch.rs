@@ -0,0 +1,441 @@
+//! file change notifications for the --follow and --watch modes.
+//! on linux the parent directory of a file, or every directory of a
+//! tree, is watched with inotify; every other platform (or a failing
+//! inotify) falls back to polling the metadata of the files.
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::encoding::{self, Encoding};
+use crate::{match_column, walk, Config};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/* ((line, column), line contents), as in the results of run_mini_grep */
+pub type LineMatch = ((usize, usize), String);
+
+/* blocks until the watched file, or a file below the watched directory,
+changes on disk */
+pub struct Watcher {
+  backend: Backend,
+}
+
+enum Backend {
+  #[cfg(target_os = "linux")]
+  Inotify(inotify::Inotify),
+  Poll(Poller),
+}
+
+impl Watcher {
+  pub fn new(path: &str) -> Watcher {
+    #[cfg(target_os = "linux")]
+    if let Ok(inotify) = inotify::Inotify::new(Path::new(path)) {
+      return Watcher { backend: Backend::Inotify(inotify) };
+    }
+    Watcher { backend: Backend::Poll(Poller::new(Path::new(path))) }
+  }
+
+  pub fn is_polling(&self) -> bool {
+    matches!(self.backend, Backend::Poll(_))
+  }
+
+  pub fn wait(&mut self) -> Result<(), Box<dyn Error>> {
+    match &mut self.backend {
+      #[cfg(target_os = "linux")]
+      Backend::Inotify(inotify) => inotify.wait(),
+      Backend::Poll(poller) => {
+        poller.wait();
+        Ok(())
+      },
+    }
+  }
+}
+
+/* last seen (path, modification time, length) of the file, or of every
+file below the directory, that a search would read */
+struct Poller {
+  path: PathBuf,
+  last: Vec<(PathBuf, SystemTime, u64)>,
+}
+
+impl Poller {
+  fn new(path: &Path) -> Poller {
+    let path = path.to_path_buf();
+    let last = Poller::stamp(&path);
+    Poller { path, last }
+  }
+
+  fn stamp(path: &Path) -> Vec<(PathBuf, SystemTime, u64)> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+      let _ = walk(path, &mut files, &mut Vec::new());
+      files.sort();
+    } else {
+      files.push(path.to_path_buf());
+    }
+    files.into_iter()
+      .filter_map(|file| {
+        let metadata = fs::metadata(&file).ok()?;
+        Some((file, metadata.modified().ok()?, metadata.len()))
+      })
+      .collect()
+  }
+
+  fn wait(&mut self) {
+    loop {
+      thread::sleep(POLL_INTERVAL);
+      let current = Poller::stamp(&self.path);
+      if current != self.last {
+        self.last = current;
+        return;
+      }
+    }
+  }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+  use std::error::Error;
+  use std::ffi::{CString, OsString};
+  use std::io;
+  use std::mem;
+  use std::os::unix::ffi::{OsStrExt, OsStringExt};
+  use std::path::{Path, PathBuf};
+
+  /* events arriving this close to each other are reported as one change */
+  const DEBOUNCE_MS: i32 = 50;
+
+  pub struct Inotify {
+    fd: i32,
+    target: Target,
+  }
+
+  enum Target {
+    File(OsString), /* name in the watched parent directory */
+    Tree(PathBuf),  /* every directory below it is watched */
+  }
+
+  impl Inotify {
+    /* watches the parent directory of a file so that editors replacing
+    the file (write to temp + rename) keep being noticed. */
+    pub fn new(path: &Path) -> io::Result<Inotify> {
+      let target = if path.is_dir() {
+        Target::Tree(path.to_path_buf())
+      } else {
+        Target::File(path.file_name()
+          .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?
+          .to_os_string())
+      };
+      let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+      if fd < 0 {
+        return Err(io::Error::last_os_error());
+      }
+      let inotify = Inotify { fd, target };
+      match &inotify.target {
+        Target::File(_) => {
+          let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+          };
+          inotify.add_watch(dir)?;
+        },
+        Target::Tree(root) => inotify.watch_tree(root)?,
+      }
+      Ok(inotify)
+    }
+
+    fn add_watch(&self, dir: &Path) -> io::Result<()> {
+      let dir = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+      let mask = libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_ATTRIB
+        | libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM;
+      if unsafe { libc::inotify_add_watch(self.fd, dir.as_ptr(), mask) } < 0 {
+        return Err(io::Error::last_os_error());
+      }
+      Ok(())
+    }
+
+    /* watches dir and the directories below it a search would enter.
+    a directory already watched keeps its watch. */
+    fn watch_tree(&self, dir: &Path) -> io::Result<()> {
+      self.add_watch(dir)?;
+      for entry in std::fs::read_dir(dir)?.flatten() {
+        let hidden = entry.file_name().as_bytes().starts_with(b".");
+        if !hidden && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+          /* a directory removed meanwhile is no change to wait for */
+          let _ = self.watch_tree(&entry.path());
+        }
+      }
+      Ok(())
+    }
+
+    pub fn wait(&mut self) -> Result<(), Box<dyn Error>> {
+      /* block until an event names our file, then swallow the burst
+      of events that usually follows a single write. */
+      while !self.read_events(-1)? {}
+      while self.read_events(DEBOUNCE_MS)? {}
+      /* directories created by the change are watched from now on */
+      if let Target::Tree(root) = &self.target {
+        self.watch_tree(root)?;
+      }
+      Ok(())
+    }
+
+    /* reads pending events, true if any of them concerns the watched file.
+    returns false when nothing arrived within timeout_ms. */
+    fn read_events(&mut self, timeout_ms: i32) -> io::Result<bool> {
+      let mut pollfd = libc::pollfd { fd: self.fd, events: libc::POLLIN, revents: 0 };
+      let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+      if ready < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+          return Ok(false);
+        }
+        return Err(err);
+      }
+      if ready == 0 {
+        return Ok(false);
+      }
+      let mut buffer = [0u8; 4096];
+      let read = unsafe {
+        libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+      };
+      if read < 0 {
+        return Err(io::Error::last_os_error());
+      }
+      let header = mem::size_of::<libc::inotify_event>();
+      let mut offset = 0;
+      let mut concerns_file = false;
+      while offset + header <= read as usize {
+        let event: libc::inotify_event = unsafe {
+          std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const libc::inotify_event)
+        };
+        let name = &buffer[offset + header..offset + header + event.len as usize];
+        /* the name is padded with NUL bytes */
+        let name = match name.iter().position(|&b| b == 0) {
+          Some(end) => &name[..end],
+          None => name,
+        };
+        concerns_file |= match &self.target {
+          Target::File(file_name) => OsString::from_vec(name.to_vec()) == *file_name,
+          /* hidden entries are not searched */
+          Target::Tree(_) => !name.starts_with(b"."),
+        };
+        offset += header + event.len as usize;
+      }
+      Ok(concerns_file)
+    }
+  }
+
+  impl Drop for Inotify {
+    fn drop(&mut self) {
+      unsafe { libc::close(self.fd) };
+    }
+  }
+}
+
+/* reads lines appended to a file since the last poll, tail -f style:
+what the file holds when following starts is skipped. line indices keep
+counting from the start of the file, so they match the ones reported by
+a regular search. */
+pub struct Follower {
+  path: String,
+  pattern: String,
+  case_insensitive: bool,
+  forced_encoding: Option<Encoding>,
+  encoding: Encoding, /* resolved when the start of the file is read */
+  offset: u64,      /* bytes of the file consumed so far */
+  line_index: usize, /* index of the next complete line */
+  pending: Vec<u8>, /* trailing bytes of a line that has no newline yet */
+}
+
+impl Follower {
+  pub fn new(config: &Config) -> Follower {
+    let mut follower = Follower {
+      path: config.file_path.clone(),
+      pattern: config.pattern.clone(),
+      case_insensitive: config.case_insensitive,
+      forced_encoding: config.encoding,
+      encoding: config.encoding.unwrap_or(Encoding::Utf8),
+      offset: 0,
+      line_index: 0,
+      pending: Vec::new(),
+    };
+    /* a file yet to be created is followed from its first line */
+    let _ = follower.read(false);
+    follower
+  }
+
+  /* matches found in the complete lines appended since the last poll,
+  in file order. a truncated (or rotated) file is read again from the start. */
+  pub fn poll(&mut self) -> Result<Vec<LineMatch>, Box<dyn Error>> {
+    self.read(true)
+  }
+
+  /* consumes the complete lines appended since the last read, matching
+  them only when report is set */
+  fn read(&mut self, report: bool) -> Result<Vec<LineMatch>, Box<dyn Error>> {
+    let mut file = File::open(&self.path)?;
+    let length = file.metadata()?.len();
+    if length < self.offset {
+      self.offset = 0;
+      self.line_index = 0;
+      self.pending.clear();
+    }
+    file.seek(SeekFrom::Start(self.offset))?;
+    let mut appended = Vec::new();
+    file.read_to_end(&mut appended)?;
+    let mut skip = 0;
+    if self.offset == 0 && !appended.is_empty() {
+      (self.encoding, skip) = encoding::resolve(&appended, self.forced_encoding);
+    }
+    self.offset += appended.len() as u64;
+    self.pending.extend_from_slice(&appended[skip..]);
+
+    let mut matches = Vec::new();
+    let complete = match self.encoding.complete_lines_len(&self.pending) {
+      Some(complete) => complete,
+      None => return Ok(matches),
+    };
+    let lines: Vec<u8> = self.pending.drain(..complete).collect();
+    let text = self.encoding.decode_lossy(&lines);
+    /* drop the final newline so split() yields exactly the complete lines */
+    for line in text[..text.len() - 1].split('\n') {
+      let line = line.strip_suffix('\r').unwrap_or(line);
+      if !report {
+        self.line_index += 1;
+        continue;
+      }
+      if let Some(column) = match_column(&self.pattern, line, self.case_insensitive) {
+        matches.push(((self.line_index, column), line.to_string()));
+      }
+      self.line_index += 1;
+    }
+    Ok(matches)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Write;
+
+  fn follower_for(path: &Path, pattern: &str) -> Follower {
+    let config = Config {
+      file_path: path.to_string_lossy().into_owned(),
+      pattern: pattern.to_string(),
+      mode: crate::Mode::Follow,
+      ..Config::default()
+    };
+    Follower::new(&config)
+  }
+
+  fn temp_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("mini-grep-{}-{}", std::process::id(), name));
+    fs::write(&path, "").unwrap();
+    path
+  }
+
+  /* hands watcher back once it woke up for change. panics after a few
+  seconds rather than waiting forever on a change going unnoticed. */
+  fn notices(mut watcher: Watcher, change: impl FnOnce()) -> Watcher {
+    let polling = watcher.is_polling();
+    let (woken, wakes) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+      watcher.wait().unwrap();
+      let _ = woken.send(watcher);
+    });
+    change();
+    wakes.recv_timeout(Duration::from_secs(5))
+      .unwrap_or_else(|_| panic!("change not noticed, polling: {}", polling))
+  }
+
+  #[test]
+  fn watching_a_directory_notices_changes_below_it() {
+    let dir = std::env::temp_dir().join(format!("mini-grep-{}-watch-dir", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/a.txt"), "a\n").unwrap();
+    let dir_name = dir.to_str().unwrap();
+    let polling = Watcher { backend: Backend::Poll(Poller::new(&dir)) };
+    for watcher in [Watcher::new(dir_name), polling] {
+      let append = |path: &str| {
+        let mut file = fs::OpenOptions::new().append(true).open(dir.join(path)).unwrap();
+        writeln!(file, "needle").unwrap();
+      };
+      let watcher = notices(watcher, || append("sub/a.txt"));
+      let watcher = notices(watcher, || {
+        fs::create_dir(dir.join("new")).unwrap();
+        fs::write(dir.join("new/b.txt"), "b\n").unwrap();
+      });
+      /* files in directories created since are watched too */
+      notices(watcher, || append("new/b.txt"));
+      fs::remove_dir_all(dir.join("new")).unwrap();
+    }
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn follow_reports_only_appended_complete_lines() {
+    let path = temp_file("follow-append");
+    let mut follower = follower_for(&path, "error");
+    fs::write(&path, "ok\nerror one\n").unwrap();
+    assert_eq!(vec![((1, 0), String::from("error one"))], follower.poll().unwrap());
+
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(file, "an error").unwrap();
+    assert!(follower.poll().unwrap().is_empty());
+    write!(file, " two\r\nfine\n").unwrap();
+    assert_eq!(vec![((2, 3), String::from("an error two"))], follower.poll().unwrap());
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn follow_starts_at_the_end_of_the_file() {
+    let path = temp_file("follow-end");
+    fs::write(&path, "error before
+ok
+error half").unwrap();
+    let mut follower = follower_for(&path, "error");
+    assert!(follower.poll().unwrap().is_empty());
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(file, " done
+error after
+").unwrap();
+    /* the line in progress when following started is reported once complete */
+    assert_eq!(
+      vec![((2, 0), String::from("error half done")), ((3, 0), String::from("error after"))],
+      follower.poll().unwrap()
+    );
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn follow_decodes_utf16_with_byte_order_mark() {
+    let path = temp_file("follow-utf16");
+    let mut follower = follower_for(&path, "é");
+    let encode = |text: &str| text.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+    let mut contents = vec![0xFF, 0xFE];
+    contents.extend(encode("a\r\nqué"));
+    fs::write(&path, &contents).unwrap();
+    assert!(follower.poll().unwrap().is_empty());
+    contents.extend(encode("\n"));
+    fs::write(&path, &contents).unwrap();
+    assert_eq!(vec![((1, 2), String::from("qué"))], follower.poll().unwrap());
+    fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn follow_restarts_after_truncation() {
+    let path = temp_file("follow-truncate");
+    let mut follower = follower_for(&path, "error");
+    fs::write(&path, "a\nb\nerror\n").unwrap();
+    assert_eq!(vec![((2, 0), String::from("error"))], follower.poll().unwrap());
+    fs::write(&path, "error\n").unwrap();
+    assert_eq!(vec![((0, 0), String::from("error"))], follower.poll().unwrap());
+    fs::remove_file(&path).unwrap();
+  }
+}
@@ -0,0 +1,298 @@
+//! access logs: a line per answered request in Common or Combined Log
+//! Format, or as a JSON object, written to stdout or to a file that is
+//! rotated once it grows past a size. common and combined lines end with
+//! the time taken to answer in microseconds, like Apache's %D.
+use std::{
+  fmt::Write as _,
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  net::IpAddr,
+  path::{Path, PathBuf},
+  sync::{Mutex, PoisonError},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{http::StatusCode, request::Request};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+  Common,
+  Combined,
+  Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogTarget {
+  Off,
+  Stdout,
+  /* FILE is renamed FILE.1 once it would grow past max_bytes, FILE.1
+  FILE.2 and so on, keeping at most keep of them */
+  File { path: PathBuf, max_bytes: u64, keep: usize },
+}
+
+/* what is logged of one answered request */
+pub struct Entry<'a> {
+  pub client: Option<IpAddr>,
+  pub received: SystemTime,
+  pub request: Option<&'a Request>, /* None when the request was unreadable */
+  pub status: StatusCode,
+  pub bytes: u64, /* of the response body sent */
+  pub duration: Duration,
+}
+
+impl Entry<'_> {
+  fn header(&self, name: &str) -> Option<&str> {
+    self.request.and_then(|request| request.headers.get(name))
+  }
+
+  /* the line logged for the entry, without its line break */
+  pub fn format(&self, format: LogFormat) -> String {
+    let client = self.client.map_or("-".to_string(), |ip| ip.to_string());
+    let request_line = self.request.map(|request| format!("{} {} {}", request.method, request.target, request.version));
+    let mut line = String::new();
+    match format {
+      LogFormat::Common | LogFormat::Combined => {
+        let _ = write!(
+          line,
+          "{} - - [{}] \"{}\" {} {}",
+          client,
+          clf_time(self.received),
+          request_line.as_deref().map_or("-".to_string(), escape_clf),
+          self.status.code(),
+          if self.bytes == 0 { "-".to_string() } else { self.bytes.to_string() },
+        );
+        if format == LogFormat::Combined {
+          let _ = write!(
+            line,
+            " \"{}\" \"{}\"",
+            self.header("Referer").map_or("-".to_string(), escape_clf),
+            self.header("User-Agent").map_or("-".to_string(), escape_clf),
+          );
+        }
+        let _ = write!(line, " {}", self.duration.as_micros());
+      },
+      LogFormat::Json => {
+        let string = |value: Option<&str>| value.map_or("null".to_string(), |value| format!("\"{}\"", escape_json(value)));
+        let _ = write!(
+          line,
+          "{{\"client\":{},\"time\":\"{}\",\"request\":{},\"status\":{},\"bytes\":{},\"referer\":{},\"user_agent\":{},\"duration_us\":{}}}",
+          string(self.client.map(|ip| ip.to_string()).as_deref()),
+          rfc3339_time(self.received),
+          string(request_line.as_deref()),
+          self.status.code(),
+          self.bytes,
+          string(self.header("Referer")),
+          string(self.header("User-Agent")),
+          self.duration.as_micros(),
+        );
+      },
+    }
+    line
+  }
+}
+
+enum Sink {
+  Off,
+  Stdout,
+  File { path: PathBuf, file: File, written: u64, max_bytes: u64, keep: usize },
+}
+
+/* shared by the workers, each entry is written as one whole line */
+pub struct AccessLog {
+  format: LogFormat,
+  sink: Mutex<Sink>,
+}
+
+impl AccessLog {
+  pub fn open(format: LogFormat, target: LogTarget) -> io::Result<AccessLog> {
+    let sink = match target {
+      LogTarget::Off => Sink::Off,
+      LogTarget::Stdout => Sink::Stdout,
+      LogTarget::File { path, max_bytes, keep } => {
+        let file = append(&path)?;
+        let written = file.metadata()?.len();
+        Sink::File { path, file, written, max_bytes, keep }
+      },
+    };
+    Ok(AccessLog { format, sink: Mutex::new(sink) })
+  }
+
+  pub fn log(&self, entry: &Entry) {
+    let mut sink = self.sink.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Sink::Off = *sink {
+      return;
+    }
+    let line = entry.format(self.format) + "\n";
+    let written = match &mut *sink {
+      Sink::Off => Ok(()),
+      Sink::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+      Sink::File { path, file, written, max_bytes, keep } => {
+        if *written > 0 && *written + line.len() as u64 > *max_bytes {
+          match rotate(path, *keep) {
+            Ok(rotated) => {
+              *file = rotated;
+              *written = 0;
+            },
+            Err(e) => eprintln!("failed to rotate {}: {}", path.display(), e),
+          }
+        }
+        *written += line.len() as u64;
+        file.write_all(line.as_bytes())
+      },
+    };
+    if let Err(e) = written {
+      eprintln!("failed to write the access log: {}", e);
+    }
+  }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+  OpenOptions::new().create(true).append(true).open(path)
+}
+
+/* shifts FILE.n to FILE.n+1, dropping the oldest, then FILE to FILE.1,
+and opens a new FILE */
+fn rotate(path: &Path, keep: usize) -> io::Result<File> {
+  let numbered = |n: usize| {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+  };
+  if keep == 0 {
+    fs::remove_file(path)?;
+    return append(path);
+  }
+  for n in (1..keep).rev() {
+    match fs::rename(numbered(n), numbered(n + 1)) {
+      Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+      _ => (),
+    }
+  }
+  fs::rename(path, numbered(1))?;
+  append(path)
+}
+
+/* quotes and bytes outside printable ASCII are escaped as Apache does */
+fn escape_clf(value: &str) -> String {
+  let mut escaped = String::new();
+  for byte in value.bytes() {
+    match byte {
+      b'"' | b'\\' => {
+        escaped.push('\\');
+        escaped.push(byte as char);
+      },
+      b' '..=b'~' => escaped.push(byte as char),
+      _ => {
+        let _ = write!(escaped, "\\x{:02x}", byte);
+      },
+    }
+  }
+  escaped
+}
+
+fn escape_json(value: &str) -> String {
+  let mut escaped = String::new();
+  for c in value.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      '\t' => escaped.push_str("\\t"),
+      c if c.is_control() => {
+        let _ = write!(escaped, "\\u{:04x}", c as u32);
+      },
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/* UTC year, month, day, hours, minutes and seconds of a time */
+fn utc(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+  let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+  let (days, secs) = ((secs / 86_400) as i64, secs % 86_400);
+  /* days to a civil date, after Howard Hinnant's days_from_civil inverse */
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let day_of_era = z.rem_euclid(146_097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let mp = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  (year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/* e.g. 10/Oct/2000:13:55:36 +0000 */
+fn clf_time(time: SystemTime) -> String {
+  const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+  let (year, month, day, hours, minutes, seconds) = utc(time);
+  format!("{:02}/{}/{}:{:02}:{:02}:{:02} +0000", day, MONTHS[month as usize - 1], year, hours, minutes, seconds)
+}
+
+/* e.g. 2000-10-10T13:55:36Z */
+fn rfc3339_time(time: SystemTime) -> String {
+  let (year, month, day, hours, minutes, seconds) = utc(time);
+  format!("{}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hours, minutes, seconds)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::request::Limits;
+
+  fn entry_for(request: Option<&Request>) -> Entry<'_> {
+    Entry {
+      client: Some(IpAddr::from([127, 0, 0, 1])),
+      received: UNIX_EPOCH + Duration::from_secs(971_186_136), /* 10 Oct 2000 13:55:36 */
+      request,
+      status: StatusCode::Ok,
+      bytes: 2326,
+      duration: Duration::from_micros(1500),
+    }
+  }
+
+  #[test]
+  fn entries_in_every_format() {
+    let mut raw = &b"GET /apache_pb.gif HTTP/1.1\r\nHost: example.com\r\nReferer: http://example.com/start.html\r\nUser-Agent: Mozilla/4.08 \"quoted\"\r\n\r\n"[..];
+    let request = Request::parse(&mut raw, &Limits::default()).unwrap();
+    let entry = entry_for(Some(&request));
+    assert_eq!(
+      "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif HTTP/1.1\" 200 2326 1500",
+      entry.format(LogFormat::Common)
+    );
+    assert_eq!(
+      "127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif HTTP/1.1\" 200 2326 \"http://example.com/start.html\" \"Mozilla/4.08 \\\"quoted\\\"\" 1500",
+      entry.format(LogFormat::Combined)
+    );
+    assert_eq!(
+      "{\"client\":\"127.0.0.1\",\"time\":\"2000-10-10T13:55:36Z\",\"request\":\"GET /apache_pb.gif HTTP/1.1\",\"status\":200,\"bytes\":2326,\"referer\":\"http://example.com/start.html\",\"user_agent\":\"Mozilla/4.08 \\\"quoted\\\"\",\"duration_us\":1500}",
+      entry.format(LogFormat::Json)
+    );
+
+    /* an unreadable request, answered with an empty body */
+    let entry = Entry { status: StatusCode::BadRequest, bytes: 0, ..entry_for(None) };
+    assert_eq!("127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] \"-\" 400 - \"-\" \"-\" 1500", entry.format(LogFormat::Combined));
+    assert!(entry.format(LogFormat::Json).contains("\"request\":null,\"status\":400,\"bytes\":0,\"referer\":null"));
+    assert_eq!("29/Feb/2024:23:59:59 +0000", clf_time(UNIX_EPOCH + Duration::from_secs(1_709_251_199)));
+  }
+
+  #[test]
+  fn log_files_are_rotated() {
+    let dir = std::env::temp_dir().join(format!("webserver-access-log-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("access.log");
+    let line_length = entry_for(None).format(LogFormat::Common).len() as u64 + 1;
+    let log = AccessLog::open(LogFormat::Common, LogTarget::File { path: path.clone(), max_bytes: 2 * line_length, keep: 2 }).unwrap();
+    for _ in 0..7 {
+      log.log(&entry_for(None));
+    }
+    let lines = |name: &str| fs::read_to_string(dir.join(name)).map(|log| log.lines().count()).unwrap_or(0);
+    /* two lines per file, the first two are gone */
+    assert_eq!((1, 2, 2, 0), (lines("access.log"), lines("access.log.1"), lines("access.log.2"), lines("access.log.3")));
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,141 @@
+//! an in-memory cache of file contents, shared by the workers.
+//! entries are keyed by path and remember the modification time and
+//! length the file had when it was read: a lookup with any other
+//! metadata misses and drops the entry, so an edited file is read again.
+//! once the cached contents outgrow the capacity, the least recently
+//! used entries are evicted.
+use std::{
+  collections::{BTreeMap, HashMap},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex, PoisonError},
+  time::SystemTime,
+};
+
+struct Cached {
+  contents: Arc<[u8]>,
+  modified: SystemTime,
+  used: u64, /* key in Lru::recency */
+}
+
+struct Lru {
+  entries: HashMap<PathBuf, Cached>,
+  recency: BTreeMap<u64, PathBuf>, /* least recently used first */
+  clock: u64,
+  size: u64, /* bytes of contents */
+}
+
+impl Lru {
+  fn remove(&mut self, path: &Path) {
+    if let Some(cached) = self.entries.remove(path) {
+      self.recency.remove(&cached.used);
+      self.size -= cached.contents.len() as u64;
+    }
+  }
+}
+
+pub struct FileCache {
+  capacity: u64,      /* bytes of contents */
+  max_file_size: u64, /* larger files are not cached */
+  lru: Mutex<Lru>,
+}
+
+impl FileCache {
+  /* a cache of capacity bytes, 0 caching nothing, holding files of up to
+  max_file_size bytes */
+  pub fn new(capacity: u64, max_file_size: u64) -> FileCache {
+    let lru = Lru { entries: HashMap::new(), recency: BTreeMap::new(), clock: 0, size: 0 };
+    FileCache { capacity, max_file_size: max_file_size.min(capacity), lru: Mutex::new(lru) }
+  }
+
+  /* whether contents of len bytes would be kept */
+  pub fn admits(&self, len: u64) -> bool {
+    len <= self.max_file_size
+  }
+
+  /* the contents of the file at path, if cached from a file with the same
+  modification time and length */
+  pub fn get(&self, path: &Path, modified: SystemTime, len: u64) -> Option<Arc<[u8]>> {
+    let mut lru = self.lru.lock().unwrap_or_else(PoisonError::into_inner);
+    let lru = &mut *lru;
+    let cached = lru.entries.get_mut(path)?;
+    if cached.modified != modified || cached.contents.len() as u64 != len {
+      lru.remove(path);
+      return None;
+    }
+    lru.clock += 1;
+    lru.recency.remove(&cached.used);
+    cached.used = lru.clock;
+    lru.recency.insert(cached.used, path.to_path_buf());
+    Some(Arc::clone(&cached.contents))
+  }
+
+  /* keeps the contents of the file at path as it was at modified, evicting
+  the least recently used files to make room */
+  pub fn insert(&self, path: &Path, modified: SystemTime, contents: Arc<[u8]>) {
+    let len = contents.len() as u64;
+    if !self.admits(len) {
+      return;
+    }
+    let mut lru = self.lru.lock().unwrap_or_else(PoisonError::into_inner);
+    lru.remove(path);
+    while lru.size + len > self.capacity {
+      let Some((_, oldest)) = lru.recency.pop_first() else { break };
+      lru.remove(&oldest);
+    }
+    lru.clock += 1;
+    let used = lru.clock;
+    lru.recency.insert(used, path.to_path_buf());
+    lru.entries.insert(path.to_path_buf(), Cached { contents, modified, used });
+    lru.size += len;
+  }
+
+  /* bytes of contents cached */
+  pub fn size(&self) -> u64 {
+    self.lru.lock().unwrap_or_else(PoisonError::into_inner).size
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, UNIX_EPOCH};
+
+  fn contents(len: usize) -> Arc<[u8]> {
+    vec![b'x'; len].into()
+  }
+
+  #[test]
+  fn least_recently_used_files_are_evicted() {
+    let cache = FileCache::new(300, 200);
+    let at = UNIX_EPOCH + Duration::from_secs(1000);
+    cache.insert(Path::new("/a"), at, contents(100));
+    cache.insert(Path::new("/b"), at, contents(100));
+    cache.insert(Path::new("/c"), at, contents(100));
+    assert!(cache.get(Path::new("/a"), at, 100).is_some()); /* b is now the oldest */
+    cache.insert(Path::new("/d"), at, contents(50));
+    assert!(cache.get(Path::new("/b"), at, 100).is_none());
+    assert_eq!(250, cache.size());
+    /* too large to be cached, nothing is evicted for it */
+    cache.insert(Path::new("/e"), at, contents(201));
+    assert!(cache.get(Path::new("/e"), at, 201).is_none());
+    assert!(cache.get(Path::new("/a"), at, 100).is_some());
+    assert!(cache.get(Path::new("/c"), at, 100).is_some());
+    assert!(cache.get(Path::new("/d"), at, 50).is_some());
+  }
+
+  #[test]
+  fn modified_files_miss() {
+    let cache = FileCache::new(1000, 1000);
+    let at = UNIX_EPOCH + Duration::from_secs(1000);
+    cache.insert(Path::new("/a"), at, contents(10));
+    assert!(cache.get(Path::new("/a"), at, 11).is_none());
+    assert_eq!(0, cache.size()); /* the stale entry is gone */
+    cache.insert(Path::new("/a"), at, contents(10));
+    assert!(cache.get(Path::new("/a"), at + Duration::from_nanos(1), 10).is_none());
+    assert_eq!(0, cache.size());
+    /* nothing at all is cached without a capacity */
+    let cache = FileCache::new(0, 1000);
+    cache.insert(Path::new("/a"), at, contents(1));
+    assert!(cache.get(Path::new("/a"), at, 1).is_none());
+  }
+}
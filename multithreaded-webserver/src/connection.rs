@@ -0,0 +1,179 @@
+//! connections from clients, over plain TCP or TLS.
+//! a Connection reads and writes the decrypted bytes, so whoever serves
+//! the requests does not care how they arrive. the TLS handshake happens
+//! on the first read or write, on the thread serving the connection.
+use std::{
+  fs,
+  io::{self, Read, Write},
+  net::TcpStream,
+  path::Path,
+  sync::Arc,
+  time::Duration,
+};
+
+use rustls::{
+  pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+  ServerConfig,
+  ServerConnection,
+  StreamOwned,
+};
+
+pub enum Connection {
+  Plain(TcpStream),
+  Tls(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Connection {
+  /* the connection for a stream accepted by a listener, TLS if it has a config */
+  pub fn accept(stream: TcpStream, tls: Option<&Arc<ServerConfig>>) -> io::Result<Connection> {
+    match tls {
+      None => Ok(Connection::Plain(stream)),
+      Some(config) => {
+        let session = ServerConnection::new(Arc::clone(config)).map_err(io::Error::other)?;
+        Ok(Connection::Tls(Box::new(StreamOwned::new(session, stream))))
+      },
+    }
+  }
+
+  /* the socket underneath, for timeouts and addresses */
+  pub fn tcp(&self) -> &TcpStream {
+    match self {
+      Connection::Plain(stream) => stream,
+      Connection::Tls(stream) => &stream.sock,
+    }
+  }
+}
+
+/* the most a dropped connection waits to send its close_notify */
+const CLOSE_NOTIFY_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl Drop for Connection {
+  /* tells a TLS client the connection ends here, not cut short. a client
+  that stopped reading does not hold the thread dropping it. */
+  fn drop(&mut self) {
+    if let Connection::Tls(stream) = self {
+      stream.conn.send_close_notify();
+      let timeout = match stream.sock.write_timeout() {
+        Ok(Some(timeout)) => timeout.min(CLOSE_NOTIFY_TIMEOUT),
+        _ => CLOSE_NOTIFY_TIMEOUT,
+      };
+      if stream.sock.set_write_timeout(Some(timeout)).is_err() {
+        return;
+      }
+      while stream.conn.wants_write() {
+        match stream.conn.write_tls(&mut stream.sock) {
+          Ok(written) if written > 0 => (),
+          _ => break, /* closed, failed or timed out */
+        }
+      }
+    }
+  }
+}
+
+impl Read for Connection {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Connection::Plain(stream) => stream.read(buf),
+      Connection::Tls(stream) => stream.read(buf),
+    }
+  }
+}
+
+impl Write for Connection {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      Connection::Plain(stream) => stream.write(buf),
+      Connection::Tls(stream) => stream.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Connection::Plain(stream) => stream.flush(),
+      Connection::Tls(stream) => stream.flush(),
+    }
+  }
+}
+
+/* the TLS config of a listener presenting the PEM certificate chain in
+cert_path, leaf first, signed by the PEM private key in key_path */
+pub fn tls_config(cert_path: &Path, key_path: &Path) -> io::Result<Arc<ServerConfig>> {
+  let invalid = |path: &Path, e: &dyn std::fmt::Display| {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+  };
+  let certs = CertificateDer::pem_slice_iter(&fs::read(cert_path)?)
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| invalid(cert_path, &e))?;
+  if certs.is_empty() {
+    return Err(invalid(cert_path, &"no certificate found"));
+  }
+  let key = PrivateKeyDer::from_pem_slice(&fs::read(key_path)?).map_err(|e| invalid(key_path, &e))?;
+  let config = ServerConfig::builder()
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .map_err(|e| invalid(key_path, &e))?;
+  Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    io::{BufRead, BufReader},
+    net::TcpListener,
+    path::PathBuf,
+    thread,
+  };
+  use rustls::{ClientConfig, ClientConnection, RootCertStore};
+
+  /* a self-signed certificate for localhost, written as PEM files */
+  fn self_signed(name: &str) -> (PathBuf, CertificateDer<'static>) {
+    let dir = std::env::temp_dir().join(format!("webserver-tls-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    fs::write(dir.join("cert.pem"), certified.cert.pem()).unwrap();
+    fs::write(dir.join("key.pem"), certified.signing_key.serialize_pem()).unwrap();
+    (dir, certified.cert.der().clone())
+  }
+
+  #[test]
+  fn tls_connections_read_and_write_plaintext() {
+    let (dir, cert) = self_signed("echo");
+    let config = tls_config(&dir.join("cert.pem"), &dir.join("key.pem")).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut connection = BufReader::new(Connection::accept(stream, Some(&config)).unwrap());
+      let mut line = String::new();
+      connection.read_line(&mut line).unwrap();
+      connection.get_mut().write_all(line.to_uppercase().as_bytes()).unwrap();
+      connection.get_mut().flush().unwrap();
+    });
+
+    let mut roots = RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let client_config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let session = ClientConnection::new(Arc::new(client_config), "localhost".try_into().unwrap()).unwrap();
+    let mut client = StreamOwned::new(session, TcpStream::connect(address).unwrap());
+    client.write_all(b"hello over tls\n").unwrap();
+    let mut reply = String::new();
+    BufReader::new(&mut client).read_line(&mut reply).unwrap();
+    assert_eq!("HELLO OVER TLS\n", reply);
+    server.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn tls_config_rejects_bad_pem_files() {
+    let (dir, _) = self_signed("bad");
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    assert!(tls_config(&cert, &key).is_ok());
+    /* a key where the certificate should be, and the other way around */
+    assert_eq!(io::ErrorKind::InvalidData, tls_config(&key, &key).unwrap_err().kind());
+    assert_eq!(io::ErrorKind::InvalidData, tls_config(&cert, &cert).unwrap_err().kind());
+    assert_eq!(io::ErrorKind::NotFound, tls_config(&dir.join("missing.pem"), &key).unwrap_err().kind());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,417 @@
+//! the event-driven mode of the webserver (--event-loop).
+//! one thread runs a tokio event loop owning every connection: waiting
+//! for a request costs a connection its buffer, not a worker, so
+//! thousands of idle keep-alive connections are cheap. complete requests
+//! are routed by the same Router as in the threaded mode, on the
+//! ThreadPool, so a slow handler like /sleep holds a worker while the
+//! other connections carry on. a file or stream body is read by the worker
+//! and handed to the event loop a chunk at a time, so the worker is held
+//! until the client has all but the last few chunks.
+use std::{
+  io,
+  net::{IpAddr, TcpListener},
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, PoisonError
+  },
+  time::{Duration, Instant, SystemTime},
+};
+
+use multithreaded_webserver::{
+  access_log::{AccessLog, Entry},
+  http::{Body, Response, StatusCode},
+  request::{Limits, Request, RequestParser},
+  router::Router,
+};
+use rustls::ServerConfig;
+use thread_pool::ThreadPool;
+use tokio::{
+  io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+  net,
+  sync::{mpsc, oneshot, watch},
+  time,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{respond, service_unavailable, Config, ACCEPT_POLL_INTERVAL, REQUEST_TIMEOUT};
+
+/* chunks of a streamed body a worker reads ahead of the client */
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+/* what the connections share */
+struct Server {
+  pool: Mutex<ThreadPool>,
+  router: Arc<Router>,
+  config: Arc<Config>,
+  access_log: Arc<AccessLog>,
+  shutdown: Arc<AtomicBool>,
+  /* turns true when the server stops accepting */
+  stopped: watch::Sender<bool>,
+  open: AtomicUsize, /* connections */
+}
+
+/* counts a connection as open while it lives */
+struct Open(Arc<Server>);
+
+impl Open {
+  fn new(server: &Arc<Server>) -> Self {
+    server.open.fetch_add(1, Ordering::SeqCst);
+    Open(Arc::clone(server))
+  }
+}
+
+impl Drop for Open {
+  fn drop(&mut self) {
+    self.0.open.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+/* serves the listeners until the shutdown flag is raised, then waits up to
+shutdown_timeout for the connections with a request in progress */
+pub fn serve(
+  listeners: Vec<(TcpListener, Option<Arc<ServerConfig>>)>,
+  pool: ThreadPool,
+  router: Arc<Router>,
+  config: Arc<Config>,
+  access_log: Arc<AccessLog>,
+  shutdown: Arc<AtomicBool>,
+) {
+  let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+  let server = Arc::new(Server {
+    pool: Mutex::new(pool),
+    router,
+    config,
+    access_log,
+    shutdown,
+    stopped: watch::Sender::new(false),
+    open: AtomicUsize::new(0),
+  });
+  let deadline = runtime.block_on(run(listeners, Arc::clone(&server)));
+  let abandoned = server.open.load(Ordering::SeqCst);
+  drop(runtime); /* drops the connections still open */
+  if abandoned > 0 {
+    eprintln!("{} connection(s) were still busy at shutdown", abandoned);
+  }
+  if let Some(server) = Arc::into_inner(server) {
+    let pool = server.pool.into_inner().unwrap_or_else(PoisonError::into_inner);
+    pool.shutdown(deadline.saturating_duration_since(Instant::now()));
+  }
+}
+
+/* returns when the connections are done or the shutdown deadline is */
+async fn run(listeners: Vec<(TcpListener, Option<Arc<ServerConfig>>)>, server: Arc<Server>) -> Instant {
+  for (listener, tls) in listeners {
+    listener.set_nonblocking(true).unwrap();
+    let listener = net::TcpListener::from_std(listener).unwrap();
+    tokio::spawn(accept(listener, tls.map(TlsAcceptor::from), Arc::clone(&server)));
+  }
+  /* signals only raise the flag, look at it between polls */
+  let mut poll = time::interval(ACCEPT_POLL_INTERVAL);
+  while !server.shutdown.load(Ordering::Relaxed) {
+    poll.tick().await;
+  }
+  server.stopped.send_replace(true);
+  let stats = server.pool.lock().unwrap_or_else(PoisonError::into_inner).stats();
+  println!(
+    "Shutting down with {} open connections, {}; waiting up to {}s for in-flight requests.",
+    server.open.load(Ordering::SeqCst),
+    stats,
+    server.config.shutdown_timeout.as_secs(),
+  );
+  let deadline = Instant::now() + server.config.shutdown_timeout;
+  while server.open.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+    poll.tick().await;
+  }
+  deadline
+}
+
+async fn accept(listener: net::TcpListener, tls: Option<TlsAcceptor>, server: Arc<Server>) {
+  let mut stopped = server.stopped.subscribe();
+  loop {
+    let (stream, address) = tokio::select! {
+      accepted = listener.accept() => match accepted {
+        Ok(accepted) => accepted,
+        Err(e) => {
+          eprintln!("failed to accept a connection: {}", e);
+          time::sleep(ACCEPT_POLL_INTERVAL).await;
+          continue;
+        },
+      },
+      _ = stopping(&mut stopped) => return,
+    };
+    let open = Open::new(&server);
+    let tls = tls.clone();
+    tokio::spawn(async move {
+      let Open(server) = &open;
+      match tls {
+        None => connection(stream, address.ip(), server).await,
+        /* nothing to serve after a failed or stalled handshake */
+        Some(tls) => if let Ok(Ok(stream)) = time::timeout(REQUEST_TIMEOUT, tls.accept(stream)).await {
+          connection(stream, address.ip(), server).await
+        },
+      }
+    });
+  }
+}
+
+/* resolves once the server stops accepting */
+async fn stopping(stopped: &mut watch::Receiver<bool>) {
+  let _ = stopped.wait_for(|stopped| *stopped).await;
+}
+
+async fn connection<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, client: IpAddr, server: &Arc<Server>) {
+  serve_requests(&mut stream, client, server).await;
+  /* ends a TLS session with close_notify */
+  let _ = time::timeout(Duration::from_secs(1), stream.shutdown()).await;
+}
+
+/* the event-driven handle_connection: the requests of a connection in
+order, pipelined ones included, with the same timeouts */
+async fn serve_requests<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, client: IpAddr, server: &Arc<Server>) {
+  let mut stopped = server.stopped.subscribe();
+  let mut buf = Vec::new();
+  let mut parser = RequestParser::new(Limits::default());
+  for served in 1.. {
+    /* the request is timed from its first byte */
+    let mut started = None;
+    let request = loop {
+      if !buf.is_empty() {
+        started.get_or_insert_with(|| (SystemTime::now(), Instant::now()));
+      }
+      match parser.parse(&mut buf) {
+        Ok(Some(request)) => break Ok(request),
+        Ok(None) => (),
+        Err(e) => match e.status() {
+          Some(status) => {
+            eprintln!("rejected request: {}", e);
+            break Err(status);
+          },
+          None => return,
+        },
+      }
+      buf.reserve(4096);
+      let read = match started {
+        /* idle: waits for keep_alive_timeout, or until the server stops */
+        None => tokio::select! {
+          read = time::timeout(server.config.keep_alive_timeout, stream.read_buf(&mut buf)) => read,
+          _ = stopping(&mut stopped) => return,
+        },
+        Some((_, start)) => time::timeout_at((start + REQUEST_TIMEOUT).into(), stream.read_buf(&mut buf)).await,
+      };
+      match read {
+        Ok(Ok(read)) if read > 0 => (),
+        _ => return, /* closed by the client, failed or too slow */
+      }
+    };
+    let (received, started) = started.unwrap_or_else(|| (SystemTime::now(), Instant::now()));
+    let answer = match request {
+      Ok(request) => match dispatch(request, served, server).await {
+        Some(answer) => answer,
+        None => return, /* the handler panicked */
+      },
+      Err(status) => {
+        let (response, keep_alive) = respond(&Err(status), served, &server.router, &server.config, &server.shutdown);
+        Answer::new(None, response, keep_alive)
+      },
+    };
+    let sent = send(stream, answer.message).await;
+    server.access_log.log(&Entry {
+      client: Some(client),
+      received,
+      request: answer.request.as_ref(),
+      status: answer.status,
+      bytes: *sent.as_ref().unwrap_or(&0),
+      duration: started.elapsed(),
+    });
+    if let Err(e) = sent {
+      eprintln!("failed to send the response: {}", e);
+      return;
+    }
+    if !answer.keep_alive {
+      return;
+    }
+  }
+}
+
+/* writes the message, returns the bytes of body sent */
+async fn send<S: AsyncWrite + Unpin>(stream: &mut S, message: Message) -> io::Result<u64> {
+  match message {
+    Message::Whole(Ok((message, bytes))) => {
+      write(stream, &message).await?;
+      Ok(bytes)
+    },
+    Message::Whole(Err(e)) => Err(e), /* nothing of it was sent */
+    Message::Chunks(mut chunks, sent) => {
+      let mut written = Ok(());
+      while let Some(chunk) = chunks.recv().await {
+        written = write(stream, &chunk).await;
+        if written.is_err() {
+          break;
+        }
+      }
+      drop(chunks); /* the worker stops at its next chunk */
+      written?;
+      sent.await.unwrap_or_else(|_| Err(io::Error::other("the response was not written whole")))
+    },
+  }
+}
+
+async fn write<S: AsyncWrite + Unpin>(stream: &mut S, bytes: &[u8]) -> io::Result<()> {
+  match time::timeout(REQUEST_TIMEOUT, async { stream.write_all(bytes).await?; stream.flush().await }).await {
+    Ok(written) => written,
+    Err(elapsed) => Err(elapsed.into()),
+  }
+}
+
+/* a response for the event loop to send */
+struct Answer {
+  request: Option<Request>, /* for the access log */
+  status: StatusCode,
+  message: Message,
+  keep_alive: bool,
+}
+
+enum Message {
+  /* serialized whole, with the bytes of body in it */
+  Whole(io::Result<(Vec<u8>, u64)>),
+  /* serialized by a worker as it reads a file or stream body, then the
+  bytes of body it wrote */
+  Chunks(mpsc::Receiver<Vec<u8>>, oneshot::Receiver<io::Result<u64>>),
+}
+
+impl Answer {
+  /* a response whose body is in memory */
+  fn new(request: Option<Request>, response: Response, keep_alive: bool) -> Answer {
+    let status = response.status;
+    let mut message = Vec::new();
+    let message = response.write_to(&mut message).map(|bytes| (message, bytes));
+    Answer { request, status, message: Message::Whole(message), keep_alive }
+  }
+}
+
+/* hands what is written to the event loop, blocking while it has
+CHUNKS_IN_FLIGHT chunks to send already */
+struct ChunkWriter(mpsc::Sender<Vec<u8>>);
+
+impl io::Write for ChunkWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.blocking_send(buf.to_vec())
+      .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the connection stopped sending"))?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/* routes the request and serializes the response on a worker, which
+stays to read a file or stream body. a full queue is answered 503 at
+once, the event loop must not wait for room. */
+async fn dispatch(request: Request, served: usize, server: &Server) -> Option<Answer> {
+  let (answer, answered) = oneshot::channel();
+  let job = {
+    let (router, config, shutdown) = (Arc::clone(&server.router), Arc::clone(&server.config), Arc::clone(&server.shutdown));
+    move || {
+      let request = Ok(request);
+      let (response, keep_alive) = respond(&request, served, &router, &config, &shutdown);
+      if !matches!(response.body, Body::File { .. } | Body::Stream(_)) {
+        let _ = answer.send(Answer::new(request.ok(), response, keep_alive));
+        return;
+      }
+      let (chunks, received) = mpsc::channel(CHUNKS_IN_FLIGHT);
+      let (sent, outcome) = oneshot::channel();
+      let message = Message::Chunks(received, outcome);
+      let streamed = Answer { request: request.ok(), status: response.status, message, keep_alive };
+      if answer.send(streamed).is_ok() {
+        let _ = sent.send(response.write_to(&mut ChunkWriter(chunks)));
+      }
+    }
+  };
+  let queued = server.pool.lock().unwrap_or_else(PoisonError::into_inner).try_execute(job);
+  match queued {
+    Ok(_) => answered.await.ok(),
+    Err(_) => {
+      eprintln!("job queue full; rejecting request");
+      Some(Answer::new(None, service_unavailable(), false))
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+  };
+  use multithreaded_webserver::access_log::AccessLog;
+
+  use crate::{routes, HELP};
+
+  /* serves with the options in args until the returned flag is raised */
+  fn start(args: &[&str]) -> (SocketAddr, Arc<AtomicBool>, thread::JoinHandle<()>) {
+    let args = ["webserver", "127.0.0.1:0", "--event-loop", "--access-log", "off"].iter().chain(args);
+    let config = Arc::new(Config::new(args.map(|arg| arg.to_string()), HELP, "webserver").unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let pool = ThreadPool::new(config.pool);
+    let router = Arc::new(routes(Arc::clone(&config)));
+    let access_log = Arc::new(AccessLog::open(config.log_format, config.access_log.clone()).unwrap());
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let server = {
+      let shutdown = Arc::clone(&shutdown);
+      thread::spawn(move || serve(vec![(listener, None)], pool, router, config, access_log, shutdown))
+    };
+    (address, shutdown, server)
+  }
+
+  fn get(stream: &mut TcpStream, connection: &str) -> String {
+    write!(stream, "GET / HTTP/1.1\r\nHost: h\r\nConnection: {}\r\n\r\n", connection).unwrap();
+    let mut response = vec![0; 64 * 1024];
+    let read = stream.read(&mut response).unwrap();
+    String::from_utf8_lossy(&response[..read]).into_owned()
+  }
+
+  #[test]
+  fn idle_connections_hold_no_worker() {
+    let (address, shutdown, server) = start(&["page/hello.html", "1"]);
+    /* far more idle connections than workers, which would starve the threaded mode */
+    let mut idle: Vec<TcpStream> = (0..50).map(|_| TcpStream::connect(address).unwrap()).collect();
+    let mut client = TcpStream::connect(address).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert!(get(&mut client, "close").starts_with("HTTP/1.1 200 OK\r\n"));
+    /* and the idle connections were kept open meanwhile */
+    for stream in &mut idle {
+      stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+      assert!(get(stream, "keep-alive").starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+    shutdown.store(true, Ordering::Relaxed);
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn large_files_are_streamed_whole() {
+    let dir = std::env::temp_dir().join(format!("webserver-event-loop-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    /* too large for the file cache, so read as it is sent */
+    let contents: Vec<u8> = (0..3 * 1024 * 1024).map(|i: u32| (i % 251) as u8).collect();
+    fs::write(dir.join("large.bin"), &contents).unwrap();
+    let (address, shutdown, server) = start(&["--root", dir.to_str().unwrap()]);
+    let mut client = TcpStream::connect(address).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    write!(client, "GET /large.bin HTTP/1.1\r\nHost: h\r\nConnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    let head_end = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(head.contains(&format!("Content-Length: {}\r\n", contents.len())));
+    assert!(response[head_end..] == contents[..]);
+    shutdown.store(true, Ordering::Relaxed);
+    server.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,291 @@
+//! static files served from a document root.
+//! request paths are resolved segment by segment: a path with a ".."
+//! segment is refused and the resolved file must still be inside the
+//! root once symbolic links are followed, so nothing outside the root
+//! can be read. a directory is answered with its index.html.
+use std::{
+  fs::File,
+  io::{self, Read},
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+  cache::FileCache,
+  http::{Body, Method, Response, StatusCode},
+  request::Request,
+};
+
+/* answers requests with files, from the cache while they are unchanged */
+pub struct Files {
+  cache: FileCache,
+  cache_control: Option<String>, /* sent along with every file */
+}
+
+impl Files {
+  pub fn new(cache: FileCache, cache_control: Option<String>) -> Files {
+    Files { cache, cache_control }
+  }
+
+  /* response for the file at the request path under root, None if there is none */
+  pub fn serve(&self, root: &Path, request: &Request) -> Option<Response> {
+    let path = resolve(root, &request.path)?;
+    if path.is_dir() {
+      /* relative links in the index are resolved against the directory.
+      the location extends the target as sent, still percent-encoded */
+      if !request.path.ends_with('/') {
+        let location = match request.target.split_once('?') {
+          Some((path, query)) => format!("{}/?{}", path, query),
+          None => format!("{}/", request.target),
+        };
+        return Some(Response::new(StatusCode::MovedPermanently).with_header("Location", &location));
+      }
+      return self.serve_file(request, &path.join("index.html"), StatusCode::Ok);
+    }
+    self.serve_file(request, &path, StatusCode::Ok)
+  }
+
+  /* the file at path answered with status, None if there is none. a 200
+  carries an ETag and Last-Modified, and is a 304 without the file when
+  the conditions of the request say the client's copy is current. */
+  pub fn serve_file(&self, request: &Request, path: &Path, status: StatusCode) -> Option<Response> {
+    let (body, modified) = match self.open(path) {
+      Ok(opened) => opened,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+      Err(e) if e.kind() == io::ErrorKind::PermissionDenied => return Some(Response::new(StatusCode::Forbidden)),
+      Err(e) => {
+        eprintln!("{}: {}", path.display(), e);
+        return Some(Response::new(StatusCode::InternalServerError));
+      },
+    };
+    let mut response = Response::new(status).with_content_type(content_type(path));
+    if let Some(cache_control) = &self.cache_control {
+      response = response.with_header("Cache-Control", cache_control);
+    }
+    if status == StatusCode::Ok {
+      let etag = etag(&body, modified);
+      response = response
+        .with_header("ETag", &etag)
+        .with_header("Last-Modified", &httpdate::fmt_http_date(modified));
+      if not_modified(request, &etag, modified) {
+        response.status = StatusCode::NotModified;
+        return Some(response);
+      }
+    }
+    Some(response.with_body(body))
+  }
+
+  /* the contents of the file at path, and when it was last modified.
+  files too large for the cache are read as they are sent. */
+  fn open(&self, path: &Path) -> io::Result<(Body, SystemTime)> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.is_dir() {
+      return Err(io::Error::from(io::ErrorKind::IsADirectory));
+    }
+    /* times before the epoch have no HTTP-date */
+    let (modified, len) = (metadata.modified()?.max(UNIX_EPOCH), metadata.len());
+    if let Some(contents) = self.cache.get(path, modified, len) {
+      return Ok((Body::Shared(contents), modified));
+    }
+    if !self.cache.admits(len) {
+      return Ok((Body::File { file, len }, modified));
+    }
+    let mut contents = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut contents)?;
+    let contents: Arc<[u8]> = contents.into();
+    /* a file changed while read is cached with the wrong length, and missed */
+    self.cache.insert(path, modified, Arc::clone(&contents));
+    Ok((Body::Shared(contents), modified))
+  }
+}
+
+/* a strong validator from the modification time and length, like nginx's */
+fn etag(body: &Body, modified: SystemTime) -> String {
+  let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+  format!("\"{:x}-{:x}\"", modified.as_nanos(), body.len().unwrap_or(0))
+}
+
+/* whether the client's copy, named by If-None-Match or else dated by
+If-Modified-Since, is current (RFC 9110, 13.2.2) */
+fn not_modified(request: &Request, etag: &str, modified: SystemTime) -> bool {
+  if !matches!(request.method, Method::Get | Method::Head) {
+    return false;
+  }
+  if request.headers.get("If-None-Match").is_some() {
+    /* weak comparison: a W/ prefix is ignored */
+    return request.headers.get_all("If-None-Match")
+      .flat_map(|tags| tags.split(','))
+      .map(|tag| tag.trim())
+      .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+  }
+  let since = request.headers.get("If-Modified-Since").and_then(|date| httpdate::parse_http_date(date).ok());
+  /* HTTP-dates are to the second */
+  let modified = UNIX_EPOCH + Duration::from_secs(modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs());
+  since.is_some_and(|since| modified <= since)
+}
+
+/* the file a (percent-decoded) request path names under root */
+fn resolve(root: &Path, request_path: &str) -> Option<PathBuf> {
+  let mut path = root.to_path_buf();
+  for segment in request_path.split('/').filter(|segment| !segment.is_empty() && *segment != ".") {
+    if segment == ".." || segment.contains(['\\', '\0']) {
+      return None;
+    }
+    path.push(segment);
+  }
+  let path = path.canonicalize().ok()?;
+  match path.starts_with(root.canonicalize().ok()?) {
+    true => Some(path),
+    false => None, /* a symbolic link out of the root */
+  }
+}
+
+pub fn content_type(path: &Path) -> &'static str {
+  let extension = path.extension()
+    .and_then(|extension| extension.to_str())
+    .map(|extension| extension.to_ascii_lowercase());
+  match extension.as_deref() {
+    Some("html" | "htm") => "text/html; charset=utf-8",
+    Some("css") => "text/css; charset=utf-8",
+    Some("js" | "mjs") => "text/javascript; charset=utf-8",
+    Some("json") => "application/json",
+    Some("txt") => "text/plain; charset=utf-8",
+    Some("md") => "text/markdown; charset=utf-8",
+    Some("csv") => "text/csv; charset=utf-8",
+    Some("xml") => "application/xml",
+    Some("svg") => "image/svg+xml",
+    Some("png") => "image/png",
+    Some("jpg" | "jpeg") => "image/jpeg",
+    Some("gif") => "image/gif",
+    Some("webp") => "image/webp",
+    Some("ico") => "image/x-icon",
+    Some("pdf") => "application/pdf",
+    Some("wasm") => "application/wasm",
+    Some("woff") => "font/woff",
+    Some("woff2") => "font/woff2",
+    Some("ttf") => "font/ttf",
+    Some("mp3") => "audio/mpeg",
+    Some("wav") => "audio/wav",
+    Some("mp4") => "video/mp4",
+    Some("webm") => "video/webm",
+    Some("zip") => "application/zip",
+    Some("gz") => "application/gzip",
+    _ => "application/octet-stream",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::request::Limits;
+  use std::fs;
+
+  fn scratch_root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("webserver-files-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("root/docs")).unwrap();
+    fs::write(dir.join("root/docs/index.html"), "<h1>docs</h1>").unwrap();
+    fs::write(dir.join("root/logo.png"), [0x89, b'P', b'N', b'G', 0, 0xFF]).unwrap();
+    fs::write(dir.join("secret.txt"), "outside").unwrap();
+    dir
+  }
+
+  fn files() -> Files {
+    Files::new(FileCache::new(1024, 1024), Some("no-cache".to_string()))
+  }
+
+  fn request(method: &str, path: &str, headers: &str) -> Request {
+    let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n{headers}\r\n");
+    Request::parse(&mut raw.as_bytes(), &Limits::default()).unwrap()
+  }
+
+  fn get(path: &str, headers: &str) -> Request {
+    request("GET", path, headers)
+  }
+
+  fn serve(root: &Path, path: &str) -> Option<Response> {
+    files().serve(root, &get(path, ""))
+  }
+
+  #[test]
+  fn serve_files_and_indexes() {
+    let dir = scratch_root("serve");
+    let root = dir.join("root");
+    let png = serve(&root, "/logo.png").unwrap();
+    assert_eq!(StatusCode::Ok, png.status);
+    assert_eq!(Some("image/png"), png.headers.get("Content-Type"));
+    assert_eq!(vec![0x89, b'P', b'N', b'G', 0, 0xFF], png.body.into_bytes().unwrap());
+    assert_eq!(b"<h1>docs</h1>", &serve(&root, "/docs/").unwrap().body.into_bytes().unwrap()[..]);
+    let redirect = serve(&root, "/docs").unwrap();
+    assert_eq!(StatusCode::MovedPermanently, redirect.status);
+    assert_eq!(Some("/docs/"), redirect.headers.get("Location"));
+    fs::create_dir(root.join("my docs%")).unwrap();
+    let redirect = serve(&root, "/my%20docs%25?sort=name").unwrap();
+    assert_eq!(Some("/my%20docs%25/?sort=name"), redirect.headers.get("Location"));
+    assert!(serve(&root, "/missing.html").is_none());
+    assert!(serve(&root, "/").is_none()); /* no index.html at the top */
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn serve_stays_inside_the_root() {
+    let dir = scratch_root("traversal");
+    let root = dir.join("root");
+    assert!(serve(&root, "/../secret.txt").is_none());
+    assert!(serve(&root, "/docs/../../secret.txt").is_none());
+    assert!(serve(&root, "/..\\secret.txt").is_none());
+    #[cfg(unix)]
+    {
+      std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link.txt")).unwrap();
+      assert!(serve(&root, "/link.txt").is_none());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn current_copies_are_not_modified() {
+    let dir = scratch_root("conditional");
+    let root = dir.join("root");
+    let files = files();
+    let response = files.serve(&root, &get("/logo.png", "")).unwrap();
+    let etag = response.headers.get("ETag").unwrap().to_string();
+    let last_modified = response.headers.get("Last-Modified").unwrap().to_string();
+    assert_eq!(Some("no-cache"), response.headers.get("Cache-Control"));
+
+    let status = |headers: &str| files.serve(&root, &get("/logo.png", headers)).unwrap().status;
+    assert_eq!(StatusCode::NotModified, status(&format!("If-None-Match: \"other\", W/{etag}\r\n")));
+    assert_eq!(StatusCode::NotModified, status("If-None-Match: *\r\n"));
+    assert_eq!(StatusCode::Ok, status("If-None-Match: \"other\"\r\n"));
+    assert_eq!(StatusCode::NotModified, status(&format!("If-Modified-Since: {last_modified}\r\n")));
+    assert_eq!(StatusCode::Ok, status("If-Modified-Since: Sat, 01 Jan 2000 00:00:00 GMT\r\n"));
+    /* If-None-Match wins over If-Modified-Since */
+    assert_eq!(StatusCode::Ok, status(&format!("If-None-Match: \"other\"\r\nIf-Modified-Since: {last_modified}\r\n")));
+    let not_modified = files.serve(&root, &get("/logo.png", &format!("If-None-Match: {etag}\r\n"))).unwrap();
+    assert_eq!(Some(etag.as_str()), not_modified.headers.get("ETag"));
+    assert!(not_modified.body.is_empty());
+
+    /* a change to the file is seen at once, cached or not */
+    fs::write(root.join("logo.png"), "a new logo").unwrap();
+    let changed = files.serve(&root, &get("/logo.png", &format!("If-None-Match: {etag}\r\n"))).unwrap();
+    assert_eq!(StatusCode::Ok, changed.status);
+    assert_ne!(Some(etag.as_str()), changed.headers.get("ETag"));
+    assert_eq!(b"a new logo", &changed.body.into_bytes().unwrap()[..]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn head_is_answered_like_get() {
+    let dir = scratch_root("head");
+    let root = dir.join("root");
+    let files = files();
+    let get = files.serve(&root, &get("/logo.png", "")).unwrap();
+    let head = files.serve(&root, &request("HEAD", "/logo.png", "")).unwrap();
+    assert_eq!((get.status, &get.headers, get.body.len()), (head.status, &head.headers, head.body.len()));
+    let etag = head.headers.get("ETag").unwrap();
+    let not_modified = files.serve(&root, &request("HEAD", "/logo.png", &format!("If-None-Match: {etag}\r\n"))).unwrap();
+    assert_eq!(StatusCode::NotModified, not_modified.status);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,666 @@
+//! the HTTP messages exchanged with a client.
+//! a Response is built from a status, header fields and a body, which is
+//! held in memory, read from a file or streamed from any reader, and
+//! serialized with the framing the body calls for: Content-Length when
+//! its length is known ahead, chunked transfer coding when it is not.
+use std::{
+  fmt,
+  fs::File,
+  io::{self, BufWriter, Read, Write},
+  sync::Arc,
+  time::{Duration, SystemTime},
+};
+
+/* the Server header of every response */
+pub const SERVER: &str = concat!("multithreaded-webserver/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+  Get,
+  Head,
+  Post,
+  Put,
+  Delete,
+  Patch,
+  Options,
+  Other(String), /* extension methods are routed by name */
+}
+
+impl Method {
+  pub fn parse(method: &str) -> Method {
+    match method {
+      "GET" => Method::Get,
+      "HEAD" => Method::Head,
+      "POST" => Method::Post,
+      "PUT" => Method::Put,
+      "DELETE" => Method::Delete,
+      "PATCH" => Method::Patch,
+      "OPTIONS" => Method::Options,
+      other => Method::Other(other.to_string()),
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    match self {
+      Method::Get => "GET",
+      Method::Head => "HEAD",
+      Method::Post => "POST",
+      Method::Put => "PUT",
+      Method::Delete => "DELETE",
+      Method::Patch => "PATCH",
+      Method::Options => "OPTIONS",
+      Method::Other(method) => method,
+    }
+  }
+}
+
+impl fmt::Display for Method {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/* header fields in the order they were added. names compare
+case-insensitively and a name may repeat. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+  pub fn new() -> Headers {
+    Headers(Vec::new())
+  }
+
+  /* panics on a line break in name or value, which would end the field
+  early and let the rest of it pass for more fields */
+  pub fn append(&mut self, name: &str, value: &str) {
+    assert!(!name.contains(['\r', '\n']) && !value.contains(['\r', '\n']), "line break in header field {:?}", name);
+    self.0.push((name.to_string(), value.to_string()));
+  }
+
+  /* first value of the field */
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self.iter().find(|(field, _)| field.eq_ignore_ascii_case(name)).map(|(_, value)| value)
+  }
+
+  /* replaces every value of the field with value */
+  pub fn set(&mut self, name: &str, value: &str) {
+    self.remove(name);
+    self.append(name, value);
+  }
+
+  pub fn remove(&mut self, name: &str) {
+    self.0.retain(|(field, _)| !field.eq_ignore_ascii_case(name));
+  }
+
+  pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+    self.iter().filter(move |(field, _)| field.eq_ignore_ascii_case(name)).map(|(_, value)| value)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusCode {
+  Ok,
+  Created,
+  Accepted,
+  NoContent,
+  MovedPermanently,
+  Found,
+  SeeOther,
+  NotModified,
+  TemporaryRedirect,
+  PermanentRedirect,
+  BadRequest,
+  Unauthorized,
+  Forbidden,
+  NotFound,
+  MethodNotAllowed,
+  RequestTimeout,
+  Conflict,
+  Gone,
+  PreconditionFailed,
+  PayloadTooLarge,
+  UriTooLong,
+  UnsupportedMediaType,
+  TooManyRequests,
+  RequestHeaderFieldsTooLarge,
+  InternalServerError,
+  NotImplemented,
+  ServiceUnavailable,
+  HttpVersionNotSupported,
+}
+
+impl StatusCode {
+  pub fn code(self) -> u16 {
+    match self {
+      StatusCode::Ok => 200,
+      StatusCode::Created => 201,
+      StatusCode::Accepted => 202,
+      StatusCode::NoContent => 204,
+      StatusCode::MovedPermanently => 301,
+      StatusCode::Found => 302,
+      StatusCode::SeeOther => 303,
+      StatusCode::NotModified => 304,
+      StatusCode::TemporaryRedirect => 307,
+      StatusCode::PermanentRedirect => 308,
+      StatusCode::BadRequest => 400,
+      StatusCode::Unauthorized => 401,
+      StatusCode::Forbidden => 403,
+      StatusCode::NotFound => 404,
+      StatusCode::MethodNotAllowed => 405,
+      StatusCode::RequestTimeout => 408,
+      StatusCode::Conflict => 409,
+      StatusCode::Gone => 410,
+      StatusCode::PreconditionFailed => 412,
+      StatusCode::PayloadTooLarge => 413,
+      StatusCode::UriTooLong => 414,
+      StatusCode::UnsupportedMediaType => 415,
+      StatusCode::TooManyRequests => 429,
+      StatusCode::RequestHeaderFieldsTooLarge => 431,
+      StatusCode::InternalServerError => 500,
+      StatusCode::NotImplemented => 501,
+      StatusCode::ServiceUnavailable => 503,
+      StatusCode::HttpVersionNotSupported => 505,
+    }
+  }
+
+  pub fn reason(self) -> &'static str {
+    match self {
+      StatusCode::Ok => "OK",
+      StatusCode::Created => "Created",
+      StatusCode::Accepted => "Accepted",
+      StatusCode::NoContent => "No Content",
+      StatusCode::MovedPermanently => "Moved Permanently",
+      StatusCode::Found => "Found",
+      StatusCode::SeeOther => "See Other",
+      StatusCode::NotModified => "Not Modified",
+      StatusCode::TemporaryRedirect => "Temporary Redirect",
+      StatusCode::PermanentRedirect => "Permanent Redirect",
+      StatusCode::BadRequest => "Bad Request",
+      StatusCode::Unauthorized => "Unauthorized",
+      StatusCode::Forbidden => "Forbidden",
+      StatusCode::NotFound => "Not Found",
+      StatusCode::MethodNotAllowed => "Method Not Allowed",
+      StatusCode::RequestTimeout => "Request Timeout",
+      StatusCode::Conflict => "Conflict",
+      StatusCode::Gone => "Gone",
+      StatusCode::PreconditionFailed => "Precondition Failed",
+      StatusCode::PayloadTooLarge => "Payload Too Large",
+      StatusCode::UriTooLong => "URI Too Long",
+      StatusCode::UnsupportedMediaType => "Unsupported Media Type",
+      StatusCode::TooManyRequests => "Too Many Requests",
+      StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+      StatusCode::InternalServerError => "Internal Server Error",
+      StatusCode::NotImplemented => "Not Implemented",
+      StatusCode::ServiceUnavailable => "Service Unavailable",
+      StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+    }
+  }
+
+  /* 204 and 304 responses end with their header fields */
+  pub fn allows_body(self) -> bool {
+    !matches!(self, StatusCode::NoContent | StatusCode::NotModified)
+  }
+}
+
+pub enum Body {
+  Empty,
+  Bytes(Vec<u8>),
+  /* bytes shared with others, e.g. a cache of file contents */
+  Shared(Arc<[u8]>),
+  /* len bytes of the file from where it is positioned */
+  File { file: File, len: u64 },
+  /* read to the end, sent chunked as its length is unknown */
+  Stream(Box<dyn Read + Send>),
+  /* the length of a body that is not sent, as in the answer to HEAD */
+  Omitted(Option<u64>),
+}
+
+impl Body {
+  /* the whole of a file */
+  pub fn file(file: File) -> io::Result<Body> {
+    let metadata = file.metadata()?;
+    if metadata.is_dir() {
+      return Err(io::Error::from(io::ErrorKind::IsADirectory));
+    }
+    Ok(Body::File { file, len: metadata.len() })
+  }
+
+  pub fn stream(reader: impl Read + Send + 'static) -> Body {
+    Body::Stream(Box::new(reader))
+  }
+
+  /* a body framed like this one, without its contents */
+  pub fn omitted(self) -> Body {
+    Body::Omitted(self.len())
+  }
+
+  /* None for a stream */
+  pub fn len(&self) -> Option<u64> {
+    match self {
+      Body::Empty => Some(0),
+      Body::Bytes(bytes) => Some(bytes.len() as u64),
+      Body::Shared(bytes) => Some(bytes.len() as u64),
+      Body::File { len, .. } => Some(*len),
+      Body::Stream(_) => None,
+      Body::Omitted(len) => *len,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == Some(0)
+  }
+
+  /* reads a file or stream body into memory */
+  pub fn into_bytes(self) -> io::Result<Vec<u8>> {
+    match self {
+      Body::Empty | Body::Omitted(_) => Ok(Vec::new()),
+      Body::Bytes(bytes) => Ok(bytes),
+      Body::Shared(bytes) => Ok(bytes.to_vec()),
+      Body::File { file, len } => {
+        let mut bytes = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut bytes)?;
+        Ok(bytes)
+      },
+      Body::Stream(mut reader) => {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(bytes)
+      },
+    }
+  }
+}
+
+impl fmt::Debug for Body {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Body::Empty => write!(f, "Empty"),
+      Body::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+      Body::Shared(bytes) => write!(f, "Shared({} bytes)", bytes.len()),
+      Body::File { len, .. } => write!(f, "File({} bytes)", len),
+      Body::Stream(_) => write!(f, "Stream"),
+      Body::Omitted(len) => write!(f, "Omitted({:?} bytes)", len),
+    }
+  }
+}
+
+impl From<Vec<u8>> for Body {
+  fn from(bytes: Vec<u8>) -> Body {
+    Body::Bytes(bytes)
+  }
+}
+
+impl From<&[u8]> for Body {
+  fn from(bytes: &[u8]) -> Body {
+    Body::Bytes(bytes.to_vec())
+  }
+}
+
+impl From<String> for Body {
+  fn from(string: String) -> Body {
+    Body::Bytes(string.into_bytes())
+  }
+}
+
+impl From<&str> for Body {
+  fn from(string: &str) -> Body {
+    Body::Bytes(string.as_bytes().to_vec())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+  Strict,
+  Lax,
+  None, /* only honoured by browsers along with Secure */
+}
+
+/* a cookie for the client to store, set by Response::with_cookie.
+Display gives the value of its Set-Cookie field, where the bytes of value
+that a cookie may not hold, and '%', are percent-encoded. */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+  pub name: String,
+  pub value: String,
+  pub path: Option<String>,
+  pub domain: Option<String>,
+  pub max_age: Option<Duration>,
+  pub expires: Option<SystemTime>,
+  pub secure: bool,
+  pub http_only: bool,
+  pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+  /* panics on a name that is not a token */
+  pub fn new(name: &str, value: &str) -> Cookie {
+    assert!(is_token(name), "invalid cookie name {:?}", name);
+    Cookie {
+      name: name.to_string(),
+      value: value.to_string(),
+      path: None,
+      domain: None,
+      max_age: None,
+      expires: None,
+      secure: false,
+      http_only: false,
+      same_site: None,
+    }
+  }
+
+  /* tells the client to forget the cookie */
+  pub fn removal(name: &str) -> Cookie {
+    Cookie::new(name, "").with_max_age(Duration::ZERO)
+  }
+
+  /* panics, like with_domain, on a ';' or a control character, which would
+  end the attribute */
+  pub fn with_path(mut self, path: &str) -> Cookie {
+    assert!(is_attribute_value(path), "invalid cookie path {:?}", path);
+    self.path = Some(path.to_string());
+    self
+  }
+
+  pub fn with_domain(mut self, domain: &str) -> Cookie {
+    assert!(is_attribute_value(domain), "invalid cookie domain {:?}", domain);
+    self.domain = Some(domain.to_string());
+    self
+  }
+
+  pub fn with_max_age(mut self, max_age: Duration) -> Cookie {
+    self.max_age = Some(max_age);
+    self
+  }
+
+  pub fn with_expires(mut self, expires: SystemTime) -> Cookie {
+    self.expires = Some(expires);
+    self
+  }
+
+  pub fn secure(mut self) -> Cookie {
+    self.secure = true;
+    self
+  }
+
+  pub fn http_only(mut self) -> Cookie {
+    self.http_only = true;
+    self
+  }
+
+  pub fn with_same_site(mut self, same_site: SameSite) -> Cookie {
+    self.same_site = Some(same_site);
+    self
+  }
+}
+
+impl fmt::Display for Cookie {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}=", self.name)?;
+    for b in self.value.bytes() {
+      if is_cookie_octet(b) && b != b'%' {
+        write!(f, "{}", b as char)?;
+      } else {
+        write!(f, "%{:02X}", b)?;
+      }
+    }
+    if let Some(path) = &self.path {
+      write!(f, "; Path={}", path)?;
+    }
+    if let Some(domain) = &self.domain {
+      write!(f, "; Domain={}", domain)?;
+    }
+    if let Some(max_age) = self.max_age {
+      write!(f, "; Max-Age={}", max_age.as_secs())?;
+    }
+    if let Some(expires) = self.expires {
+      write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+    }
+    if self.secure {
+      write!(f, "; Secure")?;
+    }
+    if self.http_only {
+      write!(f, "; HttpOnly")?;
+    }
+    match self.same_site {
+      Some(SameSite::Strict) => write!(f, "; SameSite=Strict"),
+      Some(SameSite::Lax) => write!(f, "; SameSite=Lax"),
+      Some(SameSite::None) => write!(f, "; SameSite=None"),
+      None => Ok(()),
+    }
+  }
+}
+
+/* header names, methods and cookie names are tokens: visible characters but separators */
+pub(crate) fn is_token(s: &str) -> bool {
+  !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/* visible characters but DQUOTE, comma, semicolon and backslash (RFC 6265) */
+fn is_cookie_octet(b: u8) -> bool {
+  b.is_ascii_graphic() && !b"\",;\\".contains(&b)
+}
+
+fn is_attribute_value(s: &str) -> bool {
+  !s.contains(|c: char| c == ';' || c.is_control())
+}
+
+#[derive(Debug)]
+pub struct Response {
+  pub status: StatusCode,
+  pub headers: Headers,
+  pub body: Body,
+}
+
+impl Response {
+  pub fn new(status: StatusCode) -> Response {
+    Response { status, headers: Headers::new(), body: Body::Empty }
+  }
+
+  pub fn html(status: StatusCode, contents: impl Into<Body>) -> Response {
+    Response::new(status)
+      .with_content_type("text/html; charset=utf-8")
+      .with_body(contents)
+  }
+
+  pub fn text(status: StatusCode, contents: impl Into<Body>) -> Response {
+    Response::new(status)
+      .with_content_type("text/plain; charset=utf-8")
+      .with_body(contents)
+  }
+
+  /* adds a field, after any of the same name */
+  pub fn with_header(mut self, name: &str, value: &str) -> Response {
+    self.headers.append(name, value);
+    self
+  }
+
+  pub fn with_content_type(mut self, content_type: &str) -> Response {
+    self.headers.set("Content-Type", content_type);
+    self
+  }
+
+  pub fn with_cookie(self, cookie: &Cookie) -> Response {
+    self.with_header("Set-Cookie", &cookie.to_string())
+  }
+
+  pub fn with_body(mut self, body: impl Into<Body>) -> Response {
+    self.body = body.into();
+    self
+  }
+
+  /* status line and headers, with Date and Server unless set already and
+  the framing of the body, then the body. returns the bytes of body sent.
+  an error halfway through the body leaves the connection unusable. */
+  pub fn write_to(self, stream: &mut impl Write) -> io::Result<u64> {
+    let Response { status, mut headers, body } = self;
+    let body = if status.allows_body() { body } else { Body::Empty };
+    if headers.get("Date").is_none() {
+      headers.append("Date", &httpdate::fmt_http_date(SystemTime::now()));
+    }
+    if headers.get("Server").is_none() {
+      headers.append("Server", SERVER);
+    }
+    /* the framing is the body's to decide */
+    headers.remove("Content-Length");
+    headers.remove("Transfer-Encoding");
+    match body.len() {
+      _ if !status.allows_body() => (),
+      Some(len) => headers.append("Content-Length", &len.to_string()),
+      None => headers.append("Transfer-Encoding", "chunked"),
+    }
+
+    let mut stream = BufWriter::new(stream);
+    write!(stream, "HTTP/1.1 {} {}\r\n", status.code(), status.reason())?;
+    for (name, value) in headers.iter() {
+      write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    stream.write_all(b"\r\n")?;
+    let sent = match body {
+      Body::Empty | Body::Omitted(_) => 0,
+      Body::Bytes(bytes) => {
+        stream.write_all(&bytes)?;
+        bytes.len() as u64
+      },
+      Body::Shared(bytes) => {
+        stream.write_all(&bytes)?;
+        bytes.len() as u64
+      },
+      Body::File { file, len } => {
+        let sent = io::copy(&mut file.take(len), &mut stream)?;
+        if sent < len {
+          /* the file shrank, the promised length can't be kept */
+          return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file ended before its length"));
+        }
+        sent
+      },
+      Body::Stream(mut reader) => write_chunked(&mut reader, &mut stream)?,
+    };
+    stream.flush()?;
+    Ok(sent)
+  }
+}
+
+/* the reader in chunks as they are read, then the last, empty chunk */
+fn write_chunked(reader: &mut dyn Read, stream: &mut impl Write) -> io::Result<u64> {
+  let mut buf = [0; 16 * 1024];
+  let mut sent = 0;
+  loop {
+    let read = match reader.read(&mut buf) {
+      Ok(0) => break,
+      Ok(read) => read,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
+    };
+    write!(stream, "{:x}\r\n", read)?;
+    stream.write_all(&buf[..read])?;
+    stream.write_all(b"\r\n")?;
+    sent += read as u64;
+  }
+  stream.write_all(b"0\r\n\r\n")?;
+  Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::UNIX_EPOCH;
+
+  fn serialize(response: Response) -> (String, u64) {
+    let mut bytes = Vec::new();
+    let sent = response.write_to(&mut bytes).unwrap();
+    (String::from_utf8(bytes).unwrap(), sent)
+  }
+
+  #[test]
+  fn responses_are_framed_by_their_body() {
+    let (message, sent) = serialize(Response::text(StatusCode::Ok, "hello").with_header("Content-Length", "99"));
+    assert!(message.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nDate: "));
+    assert!(message.contains(&format!("\r\nServer: {}\r\n", SERVER)));
+    assert!(message.ends_with("\r\nContent-Length: 5\r\n\r\nhello"));
+    assert!(!message.contains("99"));
+    assert_eq!(5, sent);
+
+    /* a stream of unknown length, chunk by chunk */
+    let stream = Body::stream(io::Read::chain(&b"hello, "[..], &b"world"[..]));
+    let (message, sent) = serialize(Response::new(StatusCode::Ok).with_body(stream).with_header("Date", "today"));
+    assert!(message.contains("\r\nDate: today\r\n"));
+    assert!(message.ends_with("\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nhello, \r\n5\r\nworld\r\n0\r\n\r\n"));
+    assert_eq!(12, sent);
+
+    /* nothing after the header fields of a 304, not even a length */
+    let (message, sent) = serialize(Response::html(StatusCode::NotModified, "ignored"));
+    assert!(message.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+    assert!(message.ends_with("\r\n\r\n") && !message.contains("Content-Length") && !message.contains("ignored"));
+    assert_eq!(0, sent);
+    let (message, _) = serialize(Response::new(StatusCode::Found).with_header("Location", "/"));
+    assert!(message.contains("\r\nLocation: /\r\n") && message.ends_with("\r\nContent-Length: 0\r\n\r\n"));
+  }
+
+  #[test]
+  fn omitted_bodies_keep_their_framing() {
+    let file = File::open(std::env::current_exe().unwrap()).unwrap();
+    let len = file.metadata().unwrap().len();
+    let (message, sent) = serialize(Response::new(StatusCode::Ok).with_body(Body::file(file).unwrap().omitted()));
+    assert!(message.contains(&format!("Content-Length: {}\r\n", len)) && message.ends_with("\r\n\r\n"));
+    assert_eq!(0, sent);
+    let (message, _) = serialize(Response::new(StatusCode::Ok).with_body(Body::stream(&b"abc"[..]).omitted()));
+    assert!(message.contains("Transfer-Encoding: chunked\r\n") && message.ends_with("\r\n\r\n"));
+  }
+
+  #[test]
+  fn file_bodies_are_sent_whole() {
+    let path = std::env::temp_dir().join(format!("webserver-http-{}.txt", std::process::id()));
+    std::fs::write(&path, "from a file").unwrap();
+    let body = Body::file(File::open(&path).unwrap()).unwrap();
+    assert_eq!(Some(11), body.len());
+    let (message, sent) = serialize(Response::new(StatusCode::Ok).with_body(body));
+    assert!(message.ends_with("\r\nContent-Length: 11\r\n\r\nfrom a file"));
+    assert_eq!(11, sent);
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn cookies_format_as_set_cookie_values() {
+    assert_eq!("id=42", Cookie::new("id", "42").to_string());
+    let cookie = Cookie::new("session", "abc")
+      .with_path("/")
+      .with_domain("example.com")
+      .with_max_age(Duration::from_secs(3600))
+      .with_expires(UNIX_EPOCH + Duration::from_secs(971_186_136))
+      .secure()
+      .http_only()
+      .with_same_site(SameSite::Lax);
+    assert_eq!(
+      "session=abc; Path=/; Domain=example.com; Max-Age=3600; Expires=Tue, 10 Oct 2000 13:55:36 GMT; Secure; HttpOnly; SameSite=Lax",
+      cookie.to_string()
+    );
+    let response = Response::new(StatusCode::Ok).with_cookie(&cookie).with_cookie(&Cookie::removal("old"));
+    let cookies: Vec<_> = response.headers.get_all("Set-Cookie").collect();
+    assert_eq!(vec![cookie.to_string().as_str(), "old=; Max-Age=0"], cookies);
+    /* a value cannot end the cookie or the field */
+    assert_eq!("id=a%20b%3B%20Secure%0D%0A%25", Cookie::new("id", "a b; Secure\r\n%").to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "invalid cookie name")]
+  fn cookie_names_are_tokens() {
+    Cookie::new("id=1; Path", "x");
+  }
+
+  #[test]
+  #[should_panic(expected = "line break in header field")]
+  fn header_values_hold_no_line_breaks() {
+    Response::new(StatusCode::Ok).with_header("Location", "/\r\nSet-Cookie: id=1");
+  }
+}
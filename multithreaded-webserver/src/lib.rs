@@ -0,0 +1,9 @@
+//! building blocks of the webserver: client connections, HTTP messages,
+//! request parsing, routing, static files and their cache, and access logs.
+pub mod access_log;
+pub mod cache;
+pub mod connection;
+pub mod files;
+pub mod http;
+pub mod request;
+pub mod router;
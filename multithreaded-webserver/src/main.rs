@@ -1,29 +1,111 @@
 use std::{
-  io::{prelude::*, BufReader},
-  net::{TcpListener, TcpStream},
+  io::{self, prelude::*, BufReader},
+  net::{IpAddr, Shutdown, TcpListener, TcpStream},
+  os::{fd::AsRawFd, unix::net::UnixStream},
   process,
   env,
-  fs,
+  path::Path,
   thread,
-  time::Duration,
-  sync::Arc
+  time::{Duration, Instant, SystemTime},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc
+  }
+};
+use rustls::ServerConfig;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use thread_pool::ThreadPool;
+use multithreaded_webserver::{
+  access_log::{AccessLog, Entry},
+  connection::{self, Connection},
+  cache::FileCache,
+  files::Files,
+  http::{Body, Method, Response, StatusCode},
+  request::{Limits, Request},
+  router::Router,
 };
-use parser::Config;
-use myhttpserver::ThreadPool;
+use parser::{Config, WhenFull};
 
-const HELP: &'static str = "
+mod event_loop;
+
+const HELP: &str = "
 webserver establishes a multithreaded webserver.
 Usage: 
-  webserver <SERVER_ADDRESS> <HTML_FILEPATH> <POOL_SIZE>
+  webserver <SERVER_ADDRESS> <HTML_FILEPATH> [POOL_SIZE]
+  webserver <SERVER_ADDRESS> --root <DIR> [HTML_FILEPATH] [POOL_SIZE]
+  webserver <SERVER_ADDRESS> --tls-address <ADDRESS> --tls-cert <PEM> --tls-key <PEM> ...
 
 Options:
   -h, --help      print this help menu
   --version       print version
+  --root <DIR>    serve the files under DIR (index.html for directories),
+                  HTML_FILEPATH, if given, is still served for /
+  --tls-address <ADDRESS>
+                  also serve HTTPS on ADDRESS, with the certificate chain
+                  in --tls-cert and its private key in --tls-key, both
+                  PEM files. a self-signed pair for trying it locally:
+                  openssl req -x509 -newkey rsa:2048 -nodes -days 30
+                    -subj /CN=localhost -keyout key.pem -out cert.pem
+
+  --event-loop    keep the connections on one event loop thread, instead
+                  of a worker each, and only hand complete requests to
+                  the workers: idle keep-alive connections cost no worker.
+                  a request finding the queue full is answered 503
+                  whatever --when-full says
+  --keep-alive-timeout <SECS>
+                  close connections idle for SECS seconds (default: 5)
+  --max-requests <N>
+                  close connections after serving N requests (default: 100)
+  --queue-size <N|unbounded>
+                  connections waiting for a worker at most (default: 64)
+  --max-workers <N>
+                  add workers, up to N, while connections wait for one
+                  (default: POOL_SIZE, a fixed pool)
+  --grow-after <MS>
+                  add a worker when a connection waited MS milliseconds
+                  (default: 200)
+  --idle-worker-timeout <SECS>
+                  retire workers above POOL_SIZE idle for SECS seconds
+                  (default: 60)
+  --when-full <block|reject>
+                  with a full queue, wait for room before accepting more
+                  connections, or answer 503 Service Unavailable
+                  (default: reject)
+  --shutdown-timeout <SECS>
+                  on SIGINT or SIGTERM, wait at most SECS seconds for
+                  the requests being served (default: 10)
+  --access-log <-|off|FILE>
+                  log every request to stdout (-), nowhere, or FILE
+                  (default: -)
+  --log-format <common|combined|json>
+                  Common or Combined Log Format, both followed by the
+                  time taken in microseconds, or JSON (default: combined)
+  --log-max-size <BYTES>
+                  rotate FILE to FILE.1, FILE.1 to FILE.2 and so on once
+                  it would grow past BYTES (default: 10485760)
+  --log-files <N> rotated log files kept (default: 5)
+  --cache-control <VALUE|off>
+                  Cache-Control sent with the pages and files, or none
+                  (default: no-cache, revalidated with ETag and
+                  Last-Modified every time)
+  --file-cache <BYTES>
+                  keep up to BYTES of the pages and files in memory while
+                  they are unchanged on disk, 0 to read them every time;
+                  files over 1048576 bytes are never kept
+                  (default: 16777216)
 
 Environment Variables:
   PAGE_404=<HTML_FILEPATH> custom path to the html 404 error page.
 ";
 
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/* suggested to clients turned away because the job queue is full */
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+/* time allowed to send a whole request once it started */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/* larger files are read as they are sent */
+const MAX_CACHED_FILE: u64 = 1024 * 1024;
+
 fn main() {
   let config = match Config::new(env::args(), HELP, "webserver") {
     Ok(config) => config,
@@ -32,132 +114,355 @@ fn main() {
       process::exit(1); /* exit with error code 1 */
     },
   };
-  let listener = TcpListener::bind(&config.server_address).unwrap();
-  let mut pool = ThreadPool::new(config.pool_size);
-  let config = Arc::new(config); /* share a reference to config between multiple threads */
-  for incoming_stream in listener.incoming().take(2) {
-    let stream = incoming_stream.unwrap();
-    let config = Arc::clone(&config);
-    println!("Connection established!");
-    pool.execute(move || {
-      handle_connection(stream, &config)
-    });
-  } 
+  let access_log = match AccessLog::open(config.log_format, config.access_log.clone()) {
+    Ok(access_log) => Arc::new(access_log),
+    Err(e) => {
+      eprintln!("failed to open the access log: {}", e);
+      process::exit(1);
+    },
+  };
+  let tls_config = config.tls.as_ref().map(|tls| match connection::tls_config(&tls.cert, &tls.key) {
+    Ok(tls_config) => tls_config,
+    Err(e) => {
+      eprintln!("failed to set up TLS: {}", e);
+      process::exit(1);
+    },
+  });
+  let mut listeners = vec![(TcpListener::bind(&config.server_address).unwrap(), None)];
+  println!("{} listening on {}", config.program_name, config.server_address);
+  if let (Some(tls), Some(tls_config)) = (&config.tls, tls_config) {
+    listeners.push((TcpListener::bind(&tls.address).unwrap(), Some(tls_config)));
+    println!("{} listening on {} (TLS)", config.program_name, tls.address);
+  }
+  let mut pool = ThreadPool::new(config.pool);
+  let config = Arc::new(config);
+  /* share the routes between multiple threads */
+  let router = Arc::new(routes(Arc::clone(&config)));
+  let shutdown = Arc::new(AtomicBool::new(false));
+  for signal in [SIGINT, SIGTERM] {
+    signal_hook::flag::register(signal, Arc::clone(&shutdown)).unwrap();
+  }
+  if config.event_loop {
+    event_loop::serve(listeners, pool, router, config, access_log, shutdown);
+    return;
+  }
+  /* accept does not return on a signal: the loop sleeps in poll until a
+  listener has a connection or a signal handler writes to the pipe, after
+  raising the shutdown flag */
+  let (wake_up, woken) = UnixStream::pair().unwrap();
+  for signal in [SIGINT, SIGTERM] {
+    signal_hook::low_level::pipe::register(signal, wake_up.try_clone().unwrap()).unwrap();
+  }
+  for (listener, _) in &listeners {
+    listener.set_nonblocking(true).unwrap();
+  }
+  while !shutdown.load(Ordering::Relaxed) {
+    let accepted = accept(&listeners);
+    if accepted.is_empty() {
+      wait_for_connections(&listeners, &woken);
+      continue;
+    }
+    for (stream, client, tls) in accepted {
+      if let Err(e) = stream.set_nonblocking(false) {
+        eprintln!("failed to set up the connection: {}", e);
+        continue;
+      }
+      let plain = tls.is_none();
+      let stream_clone = stream.try_clone();
+      let job = {
+        let (router, config, shutdown) = (Arc::clone(&router), Arc::clone(&config), Arc::clone(&shutdown));
+        let access_log = Arc::clone(&access_log);
+        move || match Connection::accept(stream, tls.as_ref()) {
+          Ok(connection) => handle_connection(connection, client, &router, &config, &access_log, &shutdown),
+          Err(e) => eprintln!("failed to set up the connection: {}", e),
+        }
+      };
+      match config.when_full {
+        WhenFull::Block => {
+          /* the connection answers for itself, its handle is not needed */
+          pool.execute(job);
+        },
+        /* the stream moves into the job, keep a handle to answer on */
+        WhenFull::Reject => match stream_clone {
+          Ok(mut rejected) => if pool.try_execute(job).is_err() {
+            /* a 503 over TLS needs a handshake, too slow for this thread */
+            if plain {
+              reject_connection(&mut rejected, client, &access_log);
+            } else {
+              eprintln!("job queue full; closing TLS connection");
+            }
+          },
+          Err(e) => {
+            eprintln!("failed to keep a handle on the connection, waiting for room instead: {}", e);
+            pool.execute(job);
+          },
+        },
+      }
+    }
+  }
+  println!("Shutting down with {}; waiting up to {}s for in-flight requests.", pool.stats(), config.shutdown_timeout.as_secs());
+  let abandoned = pool.shutdown(config.shutdown_timeout);
+  if abandoned > 0 {
+    eprintln!("{} request(s) were still running at shutdown", abandoned);
+  }
 }
 
-fn handle_connection(mut stream: TcpStream, config: &Config) {
-  let buf_reader = BufReader::new(&mut stream);
-  let request_line = buf_reader.lines().next().unwrap().unwrap();
-  match request_line.as_str() {
-    "GET / HTTP/1.1" => post_request(
-      &mut stream, 
-      "HTTP/1.1 200 OK",
-      &config.html_page
-    ),
-    "GET /sleep HTTP/1.1" => thread::sleep(Duration::from_secs(5)),
-    _ => post_request(
-      &mut stream, 
-      "HTTP/1.1 404 NOT FOUND",
-      &config.error_page
-    )
+/* blocks until a listener has a connection to accept or woken is readable */
+fn wait_for_connections(listeners: &[(TcpListener, Option<Arc<ServerConfig>>)], woken: &UnixStream) {
+  let mut fds: Vec<_> = listeners.iter()
+    .map(|(listener, _)| listener.as_raw_fd())
+    .chain([woken.as_raw_fd()])
+    .map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+    .collect();
+  /* SAFETY: fds points to fds.len() initialized pollfd structs */
+  if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+    let e = io::Error::last_os_error();
+    if e.kind() != io::ErrorKind::Interrupted {
+      eprintln!("failed to wait for connections: {}", e);
+      thread::sleep(ACCEPT_POLL_INTERVAL);
+    }
   }
 }
 
-fn post_request(stream: &mut TcpStream, status_line: &str, filepath: &str) {
-  let contents = fs::read_to_string(filepath)
-    .unwrap();
-  let length = contents.len();  
-  let response = format!("\
-    {status_line}\r\nContent-Length: \
-    {length}\r\n\r\n{contents}"
-  );
-  stream.write_all(response.as_bytes())
-    .unwrap();
+/* polls every listener once, returns the connections waiting on them
+with the TLS config of their listener */
+fn accept(listeners: &[(TcpListener, Option<Arc<ServerConfig>>)]) -> Vec<(TcpStream, IpAddr, Option<Arc<ServerConfig>>)> {
+  let mut accepted = Vec::new();
+  for (listener, tls) in listeners {
+    match listener.accept() {
+      Ok((stream, address)) => accepted.push((stream, address.ip(), tls.clone())),
+      Err(e) if e.kind() != io::ErrorKind::WouldBlock => eprintln!("failed to accept a connection: {}", e),
+      Err(_) => (),
+    }
+  }
+  accepted
 }
 
-mod myhttpserver {
-  use std::{
-    thread,
-    sync::{mpsc, Arc, Mutex}
-  };
+/* answers 503 to a connection no worker can take right now */
+fn reject_connection(stream: &mut TcpStream, client: IpAddr, access_log: &AccessLog) {
+  eprintln!("job queue full; rejecting connection");
+  let (received, started) = (SystemTime::now(), Instant::now());
+  let response = service_unavailable();
+  let status = response.status;
+  /* the accept loop must not wait on a slow client */
+  let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+  let sent = response.write_to(stream);
+  /* closing with request bytes unread resets the connection, which can
+  destroy the 503 before the client reads it: end the response with a FIN
+  and discard what the client sent so far */
+  let _ = stream.shutdown(Shutdown::Write);
+  discard_readable(stream);
+  access_log.log(&Entry {
+    client: Some(client),
+    received,
+    request: None, /* never read */
+    status,
+    bytes: sent.as_ref().copied().unwrap_or(0),
+    duration: started.elapsed(),
+  });
+}
 
-  pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>
-  }
-  impl Drop for ThreadPool {
-    /* all threads should join a locked state 
-    to finish their jobs before being droped 
-    by the main thread. */
-    fn drop(&mut self) {
-      drop(self.sender.take()); // drop sender of the channel
-      for worker in &mut self.workers {
-        println!("Shutting down worker {}", worker.id);
-        /* call take on option to get the val in Some(val) and 
-        leave None value in the place of Option<thread::JoinHandle<()>. */
-        if let Some(thread) = worker.thread.take() {
-          thread.join().unwrap();
-        }
-      }
+/* reads and drops what already arrived on the stream, without waiting */
+fn discard_readable(stream: &mut TcpStream) {
+  if stream.set_nonblocking(true).is_err() {
+    return;
+  }
+  let mut buf = [0; 4096];
+  /* a client still sending gets the reset, not the accept loop */
+  for _ in 0..16 {
+    match stream.read(&mut buf) {
+      Ok(read) if read > 0 => (),
+      _ => return, /* nothing more for now, closed or failed */
     }
   }
-  impl ThreadPool {
-    pub fn new(pool_size: usize) -> Self {
-      assert!(pool_size>0);
-      let mut workers = Vec::with_capacity(pool_size);
-      let (sender, receiver) = mpsc::channel();
-      let receiver = Arc::new(Mutex::new(receiver));
-      for id in 0..pool_size {
-        workers.push(Worker::new(id, Arc::clone(&receiver)))
-      }
-      let sender = Some(sender);
-      ThreadPool { workers, sender }
+}
+
+/* the answer when no worker can take more work */
+fn service_unavailable() -> Response {
+  Response::html(StatusCode::ServiceUnavailable, StatusCode::ServiceUnavailable.reason())
+    .with_header("Retry-After", &RETRY_AFTER.as_secs().to_string())
+    .with_header("Connection", "close")
+}
+
+/* the endpoints served by the webserver */
+fn routes(config: Arc<Config>) -> Router {
+  let mut router = Router::new();
+  let files = Arc::new(Files::new(FileCache::new(config.file_cache, MAX_CACHED_FILE), config.cache_control.clone()));
+  if let Some(html_page) = &config.html_page {
+    let (page, pages) = (html_page.clone(), Arc::clone(&files));
+    router.get("/", move |request, _| html_page_response(&pages, request, StatusCode::Ok, &page));
+    let (page, pages) = (html_page.clone(), Arc::clone(&files));
+    router.get("/sleep", move |request, _| {
+      thread::sleep(Duration::from_secs(5));
+      html_page_response(&pages, request, StatusCode::Ok, &page)
+    });
+  }
+  if let Some(root) = &config.root {
+    let root = root.clone();
+    let (config, files) = (Arc::clone(&config), Arc::clone(&files));
+    router.get("/*", move |request, _| {
+      files.serve(&root, request)
+        .unwrap_or_else(|| html_page_response(&files, request, StatusCode::NotFound, &config.error_page))
+    });
+  }
+  router.not_found(move |request, _| html_page_response(&files, request, StatusCode::NotFound, &config.error_page));
+  router
+}
+
+/* serves the requests of a connection in order, pipelined ones included,
+until the client or the server asks to close it. a worker is held at most
+keep_alive_timeout waiting for a request, REQUEST_TIMEOUT reading it, and
+REQUEST_TIMEOUT for each write of a client that stopped reading. */
+fn handle_connection(
+  connection: Connection,
+  client: IpAddr,
+  router: &Router,
+  config: &Config,
+  access_log: &AccessLog,
+  shutdown: &AtomicBool,
+) {
+  if let Err(e) = connection.tcp().set_write_timeout(Some(REQUEST_TIMEOUT)) {
+    eprintln!("failed to set up the connection: {}", e);
+    return;
+  }
+  let mut reader = BufReader::new(DeadlineReader { connection, deadline: Instant::now() });
+  for served in 1.. {
+    reader.get_mut().deadline = Instant::now() + config.keep_alive_timeout;
+    match reader.fill_buf() {
+      Ok([]) | Err(_) => return, /* closed by the client, or idle for too long */
+      Ok(_) => (),
     }
-    pub fn execute<F>(&mut self, fun: F) 
-    where
-      F: FnOnce() + Send + 'static
-    {
-      let job = Box::new(fun);
-      self.sender.as_ref().unwrap().send(job).unwrap();
+    /* the request is timed from its first byte */
+    let (received, started) = (SystemTime::now(), Instant::now());
+    reader.get_mut().deadline = started + REQUEST_TIMEOUT;
+    let request = match Request::parse(&mut reader, &Limits::default()) {
+      Ok(request) => Ok(request),
+      Err(e) => match e.status() {
+        Some(status) => {
+          eprintln!("rejected request: {}", e);
+          Err(status)
+        },
+        None => return, /* the client is gone, nobody to answer */
+      },
+    };
+    let (response, keep_alive) = respond(&request, served, router, config, shutdown);
+    let status = response.status;
+    let sent = response.write_to(&mut reader.get_mut().connection);
+    access_log.log(&Entry {
+      client: Some(client),
+      received,
+      request: request.as_ref().ok(),
+      status,
+      bytes: sent.as_ref().copied().unwrap_or(0),
+      duration: started.elapsed(),
+    });
+    if let Err(e) = sent {
+      eprintln!("failed to send the response: {}", e);
+      return;
+    }
+    if !keep_alive {
+      return;
     }
   }
-  pub struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>
-  }
-  impl Worker {
-    /* use Arc because we need a reference pointer that can be shared between multilpe threads to the same channel receiver */
-    pub fn new<'a>(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Self {
-      let thread = thread::spawn(move || {
-        loop { /* loop waiting for new jobs */
-          let received_message = receiver.lock().unwrap().recv();
-          match received_message {
-            Ok(job) => {
-              println!("Worker {id} got a job; executing.");
-              job();
-            },
-            Err(_) => {
-              println!("Worker {id} disconnected; shutting down.");
-              break; /* exit loop if receiver/sender were droped causing the channel to close. */
-            } 
-          }
-        }
-      });
-      let thread = Some(thread);
-      Worker { id, thread }
+}
+
+/* the response to the served-th request of a connection, or to the
+status its parse error deserves, and whether the connection stays open */
+fn respond(
+  request: &Result<Request, StatusCode>,
+  served: usize,
+  router: &Router,
+  config: &Config,
+  shutdown: &AtomicBool,
+) -> (Response, bool) {
+  let (response, keep_alive) = match request {
+    Ok(request) => {
+      let keep_alive = request.keep_alive()
+        && served < config.max_requests
+        && !shutdown.load(Ordering::Relaxed);
+      let mut response = router.handle(request);
+      /* HTTP/1.0 has no chunked coding: a stream is read whole to be sent with its length */
+      if request.version == "HTTP/1.0" && response.body.len().is_none() {
+        response.body = match response.body.into_bytes() {
+          Ok(bytes) => Body::Bytes(bytes),
+          Err(e) => {
+            eprintln!("failed to read the response body: {}", e);
+            return (Response::html(StatusCode::InternalServerError, "Internal Server Error").with_header("Connection", "close"), false);
+          },
+        };
+      }
+      /* HEAD is answered as GET, but for the body */
+      if request.method == Method::Head {
+        response.body = response.body.omitted();
+      }
+      (response, keep_alive)
+    },
+    Err(status) => (Response::html(*status, status.reason()), false),
+  };
+  (response.with_header("Connection", if keep_alive { "keep-alive" } else { "close" }), keep_alive)
+}
+
+/* reads from a connection until a deadline, however slowly the client sends */
+struct DeadlineReader {
+  connection: Connection,
+  deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let remaining = self.deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+      return Err(io::Error::new(io::ErrorKind::TimedOut, "connection timed out"));
     }
+    self.connection.tcp().set_read_timeout(Some(remaining))?;
+    self.connection.read(buf)
+  }
+}
+
+fn html_page_response(files: &Files, request: &Request, status: StatusCode, filepath: &str) -> Response {
+  match files.serve_file(request, Path::new(filepath), status) {
+    Some(response) => response,
+    None => {
+      eprintln!("{}: not found", filepath);
+      Response::html(StatusCode::InternalServerError, "Internal Server Error")
+    },
   }
-  type Job = Box<dyn FnOnce() + Send + 'static>;
 }
+
 mod parser {
-  use std::env;  
+  use std::{env, path::PathBuf, time::Duration};
+  use multithreaded_webserver::access_log::{LogFormat, LogTarget};
+  use thread_pool::PoolOptions;
   pub struct Config {
     pub server_address: String,
-    pub html_page: String,
+    pub html_page: Option<String>, /* served for "/", optional with a root */
+    pub root: Option<PathBuf>,     /* document root of --root mode */
     pub error_page: String,
-    pub pool_size: usize,
+    pub pool: PoolOptions, /* POOL_SIZE is the minimum number of workers */
+    pub keep_alive_timeout: Duration,
+    pub max_requests: usize,
+    pub when_full: WhenFull,
+    pub shutdown_timeout: Duration,
+    pub access_log: LogTarget,
+    pub log_format: LogFormat,
+    pub tls: Option<TlsOptions>,
+    pub event_loop: bool,
+    pub cache_control: Option<String>,
+    pub file_cache: u64, /* bytes */
     pub program_name: String,
   }
+  /* the HTTPS listener served next to the plain one */
+  pub struct TlsOptions {
+    pub address: String,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+  }
+  /* what the accept loop does while the job queue is full */
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub enum WhenFull {
+    Block,
+    Reject,
+  }
   impl Config {
     pub fn new(
       mut args: impl Iterator<Item = String>, 
@@ -165,23 +470,170 @@ mod parser {
       program_name: &str,
     ) -> Result<Config, &'static str> {
         args.next();
-        let server_address = match args.next() {
-          Some(arg) => {
-            match arg.as_str() {
-              "-h" | "--help" => return Err(help),
-              "--version" => {
-                let vers = format!("{} v{}", program_name, env!("CARGO_PKG_VERSION"));
-                return Err(string_to_static_str(vers));
-              },
-              _ => arg,
-            }
-          },
+        let mut positionals = Vec::new();
+        let mut root = None;
+        let mut keep_alive_timeout = Duration::from_secs(5);
+        let mut max_requests = 100;
+        let mut queue_size = Some(64);
+        let mut max_workers = None;
+        let mut grow_after = Duration::from_millis(200);
+        let mut idle_worker_timeout = Duration::from_secs(60);
+        let mut when_full = WhenFull::Reject;
+        let mut shutdown_timeout = Duration::from_secs(10);
+        let mut access_log = None;
+        let mut log_format = LogFormat::Combined;
+        let mut log_max_size = 10 * 1024 * 1024;
+        let mut log_files = 5;
+        let (mut tls_address, mut tls_cert, mut tls_key) = (None, None, None);
+        let mut event_loop = false;
+        let mut cache_control = Some(String::from("no-cache"));
+        let mut file_cache = 16 * 1024 * 1024;
+        while let Some(arg) = args.next() {
+          /* options take their value as the next argument or after '=' */
+          let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+          };
+          match option.as_str() {
+            "-h" | "--help" => return Err(help),
+            "--version" => {
+              let vers = format!("{} v{}", program_name, env!("CARGO_PKG_VERSION"));
+              return Err(string_to_static_str(vers));
+            },
+            "--root" => match inline_value.or_else(|| args.next()) {
+              Some(dir) => root = Some(PathBuf::from(dir)),
+              None => return Err("--root requires a directory"),
+            },
+            "--event-loop" => event_loop = true,
+            "--keep-alive-timeout" => match inline_value.or_else(|| args.next()).map(|secs| secs.parse()) {
+              /* no request could ever arrive within 0 seconds */
+              Some(Ok(secs)) if secs > 0 => keep_alive_timeout = Duration::from_secs(secs),
+              _ => return Err("--keep-alive-timeout requires a positive number of seconds"),
+            },
+            "--max-requests" => match inline_value.or_else(|| args.next()).map(|n| n.parse()) {
+              Some(Ok(n)) if n > 0 => max_requests = n,
+              _ => return Err("--max-requests requires a positive number"),
+            },
+            "--queue-size" => match inline_value.or_else(|| args.next()) {
+              Some(n) if n == "unbounded" => queue_size = None,
+              Some(n) if n.parse::<usize>().is_ok() => queue_size = n.parse().ok(),
+              _ => return Err("--queue-size requires a number or unbounded"),
+            },
+            "--max-workers" => match inline_value.or_else(|| args.next()).map(|n| n.parse()) {
+              Some(Ok(n)) => max_workers = Some(n),
+              _ => return Err("--max-workers requires a number"),
+            },
+            "--grow-after" => match inline_value.or_else(|| args.next()).map(|ms| ms.parse()) {
+              Some(Ok(ms)) => grow_after = Duration::from_millis(ms),
+              _ => return Err("--grow-after requires a number of milliseconds"),
+            },
+            "--idle-worker-timeout" => match inline_value.or_else(|| args.next()).map(|secs| secs.parse()) {
+              /* with 0, idle workers would retire at once and the rest spin */
+              Some(Ok(secs)) if secs > 0 => idle_worker_timeout = Duration::from_secs(secs),
+              _ => return Err("--idle-worker-timeout requires a positive number of seconds"),
+            },
+            "--when-full" => match inline_value.or_else(|| args.next()).as_deref() {
+              Some("block") => when_full = WhenFull::Block,
+              Some("reject") => when_full = WhenFull::Reject,
+              _ => return Err("--when-full requires block or reject"),
+            },
+            "--shutdown-timeout" => match inline_value.or_else(|| args.next()).map(|secs| secs.parse()) {
+              Some(Ok(secs)) => shutdown_timeout = Duration::from_secs(secs),
+              _ => return Err("--shutdown-timeout requires a number of seconds"),
+            },
+            "--access-log" => match inline_value.or_else(|| args.next()) {
+              Some(target) => access_log = Some(target),
+              None => return Err("--access-log requires -, off or a file"),
+            },
+            "--log-format" => match inline_value.or_else(|| args.next()).as_deref() {
+              Some("common") => log_format = LogFormat::Common,
+              Some("combined") => log_format = LogFormat::Combined,
+              Some("json") => log_format = LogFormat::Json,
+              _ => return Err("--log-format requires common, combined or json"),
+            },
+            "--log-max-size" => match inline_value.or_else(|| args.next()).map(|bytes| bytes.parse()) {
+              Some(Ok(bytes)) if bytes > 0 => log_max_size = bytes,
+              _ => return Err("--log-max-size requires a positive number of bytes"),
+            },
+            "--tls-address" => match inline_value.or_else(|| args.next()) {
+              Some(address) => tls_address = Some(address),
+              None => return Err("--tls-address requires an address"),
+            },
+            "--tls-cert" => match inline_value.or_else(|| args.next()) {
+              Some(path) => tls_cert = Some(PathBuf::from(path)),
+              None => return Err("--tls-cert requires a PEM file"),
+            },
+            "--tls-key" => match inline_value.or_else(|| args.next()) {
+              Some(path) => tls_key = Some(PathBuf::from(path)),
+              None => return Err("--tls-key requires a PEM file"),
+            },
+            "--log-files" => match inline_value.or_else(|| args.next()).map(|n| n.parse()) {
+              Some(Ok(n)) => log_files = n,
+              _ => return Err("--log-files requires a number"),
+            },
+            "--cache-control" => match inline_value.or_else(|| args.next()) {
+              Some(value) if value == "off" => cache_control = None,
+              Some(value) if !value.is_empty() && !value.contains(['\r', '\n']) => cache_control = Some(value),
+              _ => return Err("--cache-control requires a value or off"),
+            },
+            "--file-cache" => match inline_value.or_else(|| args.next()).map(|bytes| bytes.parse()) {
+              Some(Ok(bytes)) => file_cache = bytes,
+              _ => return Err("--file-cache requires a number of bytes"),
+            },
+            _ if option.starts_with("--") => return Err(help),
+            _ => positionals.push(arg),
+          }
+        }
+        let mut positionals = positionals.into_iter();
+        let server_address = match positionals.next() {
+          Some(arg) => arg,
           None => return Err(help),
         };
-        let html_page = args.next().unwrap();
-        let pool_size= match args.next() {
-            Some(arg) => arg.parse().unwrap(),
-            _ => 5
+        let mut html_page = positionals.next();
+        let mut pool_size = positionals.next();
+        /* with a root the page can be left out: a lone number is the pool size */
+        if root.is_some() && pool_size.is_none() && html_page.as_ref().is_some_and(|arg| arg.parse::<usize>().is_ok()) {
+          pool_size = html_page.take();
+        }
+        if html_page.is_none() && root.is_none() {
+          return Err(help);
+        }
+        if positionals.next().is_some() {
+          return Err(help);
+        }
+        let pool_size = match pool_size {
+          Some(arg) => match arg.parse() {
+            Ok(size) if size > 0 => size,
+            _ => return Err("POOL_SIZE must be a positive number"),
+          },
+          _ => 5
+        };
+        let max_workers = max_workers.unwrap_or(pool_size);
+        if max_workers < pool_size {
+          return Err("--max-workers must be at least POOL_SIZE");
+        }
+        let pool = PoolOptions {
+          max_workers,
+          queue_capacity: queue_size,
+          grow_after,
+          idle_timeout: idle_worker_timeout,
+          verbose: true,
+          ..PoolOptions::fixed(pool_size)
+        };
+        if let Some(dir) = &root {
+          if !dir.is_dir() {
+            return Err("--root must be an existing directory");
+          }
+        }
+        let access_log = match access_log.as_deref() {
+          None | Some("-") => LogTarget::Stdout,
+          Some("off") => LogTarget::Off,
+          Some(path) => LogTarget::File { path: PathBuf::from(path), max_bytes: log_max_size, keep: log_files },
+        };
+        let tls = match (tls_address, tls_cert, tls_key) {
+          (Some(address), Some(cert), Some(key)) => Some(TlsOptions { address, cert, key }),
+          (None, None, None) => None,
+          _ => return Err("--tls-address, --tls-cert and --tls-key go together"),
         };
         let error_page = match env::var("PAGE_404") {
           Ok(val) => val,
@@ -190,8 +642,19 @@ mod parser {
         Ok(Config {
           server_address,
           html_page,
+          root,
           error_page,
-          pool_size,
+          pool,
+          keep_alive_timeout,
+          max_requests,
+          when_full,
+          shutdown_timeout,
+          access_log,
+          log_format,
+          tls,
+          event_loop,
+          cache_control,
+          file_cache,
           program_name: String::from(program_name)
         })
       }
@@ -200,4 +663,4 @@ mod parser {
   fn string_to_static_str(s: String) -> &'static str {
     Box::leak(s.into_boxed_str())
   }
-}
\ No newline at end of file
+}
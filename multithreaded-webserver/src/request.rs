@@ -0,0 +1,718 @@
+//! parsing of HTTP/1.1 requests read from a connection.
+//! every part of a request is bounded by Limits, so a client cannot make
+//! a worker buffer an arbitrary amount of data; a request that is
+//! malformed or too large is reported as a ParseError carrying the
+//! status of the response it deserves.
+use std::{
+  error::Error,
+  fmt,
+  io::{self, BufRead, Read},
+  mem,
+};
+
+use crate::http::{is_token, Headers, Method, StatusCode};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+  pub max_request_line: usize, /* bytes, without the line break */
+  pub max_header_bytes: usize, /* all header lines together */
+  pub max_headers: usize,
+  pub max_body: usize,
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Limits {
+      max_request_line: 8 * 1024,
+      max_header_bytes: 16 * 1024,
+      max_headers: 100,
+      max_body: 1024 * 1024,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+  Closed, /* the connection ended before a request started */
+  Io(io::Error),
+  Malformed(&'static str),
+  UriTooLong,
+  HeadersTooLarge,
+  BodyTooLarge,
+  UnsupportedVersion,
+  UnsupportedTransferEncoding,
+}
+
+impl ParseError {
+  /* status of the response to send back, None when nobody is listening */
+  pub fn status(&self) -> Option<StatusCode> {
+    match self {
+      ParseError::Closed | ParseError::Io(_) => None,
+      ParseError::Malformed(_) => Some(StatusCode::BadRequest),
+      ParseError::UriTooLong => Some(StatusCode::UriTooLong),
+      ParseError::HeadersTooLarge => Some(StatusCode::RequestHeaderFieldsTooLarge),
+      ParseError::BodyTooLarge => Some(StatusCode::PayloadTooLarge),
+      ParseError::UnsupportedVersion => Some(StatusCode::HttpVersionNotSupported),
+      ParseError::UnsupportedTransferEncoding => Some(StatusCode::NotImplemented),
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ParseError::Closed => write!(f, "connection closed"),
+      ParseError::Io(e) => write!(f, "{}", e),
+      ParseError::Malformed(reason) => write!(f, "malformed request: {}", reason),
+      ParseError::UriTooLong => write!(f, "request line too long"),
+      ParseError::HeadersTooLarge => write!(f, "request headers too large"),
+      ParseError::BodyTooLarge => write!(f, "request body too large"),
+      ParseError::UnsupportedVersion => write!(f, "unsupported HTTP version"),
+      ParseError::UnsupportedTransferEncoding => write!(f, "unsupported transfer encoding"),
+    }
+  }
+}
+
+impl Error for ParseError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      ParseError::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for ParseError {
+  fn from(e: io::Error) -> Self {
+    ParseError::Io(e)
+  }
+}
+
+#[derive(Debug)]
+pub struct Request {
+  pub method: Method,
+  pub target: String, /* as sent, e.g. "/search?q=rust%20book" */
+  pub path: String,   /* percent-decoded target, without the query */
+  pub query: Vec<(String, String)>,
+  pub version: String,
+  pub headers: Headers,
+  pub body: Vec<u8>,
+}
+
+impl Request {
+  /* reads one request: request line, headers up to the empty line, then
+  the body announced by Content-Length or Transfer-Encoding: chunked. */
+  pub fn parse(reader: &mut impl BufRead, limits: &Limits) -> Result<Request, ParseError> {
+    let mut request = Request::parse_head(reader, limits)?;
+    request.body = match framing(&request.headers, limits)? {
+      Framing::Length(length) => {
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).map_err(|e| match e.kind() {
+          io::ErrorKind::UnexpectedEof => ParseError::Malformed("truncated body"),
+          _ => ParseError::Io(e),
+        })?;
+        body
+      },
+      Framing::Chunked => read_chunked(reader, limits)?,
+    };
+    Ok(request)
+  }
+
+  /* the request line and headers, up to the empty line; the body is left unread */
+  fn parse_head(reader: &mut impl BufRead, limits: &Limits) -> Result<Request, ParseError> {
+    /* clients may send line breaks between requests, they count against
+    the request line */
+    let mut max_request_line = limits.max_request_line;
+    let mut request_line = String::new();
+    while request_line.is_empty() {
+      request_line = match read_line(reader, max_request_line)? {
+        Line::Complete(line) if line.is_empty() => {
+          max_request_line = max_request_line.checked_sub(2).ok_or(ParseError::UriTooLong)?;
+          line
+        },
+        Line::Complete(line) => line,
+        Line::TooLong => return Err(ParseError::UriTooLong),
+        Line::Eof(line) if line.is_empty() => return Err(ParseError::Closed),
+        Line::Eof(_) => return Err(ParseError::Malformed("truncated request line")),
+      };
+    }
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+      (Some(method), Some(target), Some(version), None) if is_token(method) => (method, target, version),
+      _ => return Err(ParseError::Malformed("invalid request line")),
+    };
+    match version {
+      "HTTP/1.1" | "HTTP/1.0" => (),
+      _ if version.starts_with("HTTP/") => return Err(ParseError::UnsupportedVersion),
+      _ => return Err(ParseError::Malformed("invalid HTTP version")),
+    }
+    if !target.starts_with('/') {
+      return Err(ParseError::Malformed("request target must be an absolute path"));
+    }
+    let (path, query) = match target.split_once('?') {
+      Some((path, query)) => (path, query),
+      None => (target, ""),
+    };
+    let path = percent_decode(path, false).ok_or(ParseError::Malformed("invalid percent-encoding"))?;
+    let query = parse_query(query).ok_or(ParseError::Malformed("invalid percent-encoding"))?;
+
+    let headers = read_headers(reader, limits)?;
+    if version == "HTTP/1.1" && headers.get("Host").is_none() {
+      return Err(ParseError::Malformed("missing Host header"));
+    }
+    Ok(Request {
+      method: Method::parse(method),
+      target: target.to_string(),
+      path,
+      query,
+      version: version.to_string(),
+      headers,
+      body: Vec::new(),
+    })
+  }
+
+  /* whether the client wants the connection kept open after the response:
+  the default of HTTP/1.1, an explicit "Connection: keep-alive" in HTTP/1.0 */
+  pub fn keep_alive(&self) -> bool {
+    let has_option = |option: &str| self.headers.get_all("Connection")
+      .flat_map(|value| value.split(','))
+      .any(|token| token.trim().eq_ignore_ascii_case(option));
+    match self.version.as_str() {
+      "HTTP/1.0" => has_option("keep-alive"),
+      _ => !has_option("close"),
+    }
+  }
+
+  /* first value of a query parameter */
+  pub fn query(&self, name: &str) -> Option<&str> {
+    self.query.iter()
+      .find(|(param, _)| param == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/* parses requests from a buffer the caller reads into without blocking,
+dropping from it what has been parsed. the head is parsed again whenever
+a line of it completes, which Limits keeps cheap; the body is then waited
+for without being looked at, a chunked one chunk by chunk, so each of its
+bytes is copied once. a request may have its limits, and
+FRAMING_ALLOWANCE, buffered at most. */
+pub struct RequestParser {
+  limits: Limits,
+  stage: Stage,
+}
+
+/* line breaks and chunk size lines, on top of what Limits bounds */
+const FRAMING_ALLOWANCE: usize = 4 * 1024;
+
+enum Stage {
+  /* bytes of blank lines dropped before the request, bytes of the buffer
+  searched for line breaks, start of its last line */
+  Head { skipped: usize, scanned: usize, line_start: usize },
+  Length { request: Request, length: usize },
+  /* the chunks parsed so far are in the request body */
+  Chunked { request: Request },
+}
+
+impl RequestParser {
+  pub fn new(limits: Limits) -> Self {
+    RequestParser { limits, stage: Stage::Head { skipped: 0, scanned: 0, line_start: 0 } }
+  }
+
+  /* the request at the start of buf, None while buf ends before it does.
+  parsed bytes are drained from buf, which must otherwise only grow
+  between calls. */
+  pub fn parse(&mut self, buf: &mut Vec<u8>) -> Result<Option<Request>, ParseError> {
+    loop {
+      match &mut self.stage {
+        Stage::Head { skipped, scanned, line_start } => {
+          /* blank lines before a request count against its request line */
+          let blank = leading_blank_lines(buf);
+          if blank > 0 {
+            *skipped += blank;
+            if *skipped > self.limits.max_request_line {
+              return Err(ParseError::UriTooLong);
+            }
+            buf.drain(..blank);
+            *scanned = scanned.saturating_sub(blank);
+            *line_start = line_start.saturating_sub(blank);
+          }
+          let completed_line = match buf[*scanned..].iter().rposition(|&b| b == b'\n') {
+            Some(at) => {
+              *line_start = *scanned + at + 1;
+              true
+            },
+            None => false,
+          };
+          *scanned = buf.len();
+          /* the head can only end or go wrong on a line break, or a line longer than the limits */
+          let max_line = self.limits.max_request_line.max(self.limits.max_header_bytes) + 2;
+          if !completed_line && buf.len() - *line_start <= max_line {
+            return self.waiting(buf);
+          }
+          let limits = Limits { max_request_line: self.limits.max_request_line - *skipped, ..self.limits };
+          let mut reader = Partial { rest: buf, exhausted: false };
+          let head = Request::parse_head(&mut reader, &limits);
+          let Some(request) = reader.complete(head)? else { return self.waiting(buf) };
+          let used = buf.len() - reader.rest.len();
+          buf.drain(..used);
+          self.stage = match framing(&request.headers, &self.limits)? {
+            Framing::Length(length) => Stage::Length { request, length },
+            Framing::Chunked => Stage::Chunked { request },
+          };
+        },
+        Stage::Length { length, .. } => {
+          if buf.len() < *length {
+            return Ok(None);
+          }
+          let Stage::Length { mut request, length } = self.reset() else { unreachable!() };
+          request.body = buf.drain(..length).collect();
+          return Ok(Some(request));
+        },
+        Stage::Chunked { request } => {
+          let mut reader = Partial { rest: buf, exhausted: false };
+          let size = read_chunk_size(&mut reader);
+          let Some(size) = reader.complete(size)? else { return self.waiting(buf) };
+          if size == 0 {
+            /* trailer fields are read and dropped */
+            let trailers = read_headers(&mut reader, &self.limits);
+            if reader.complete(trailers)?.is_none() {
+              return self.waiting(buf);
+            }
+            let used = buf.len() - reader.rest.len();
+            buf.drain(..used);
+            let Stage::Chunked { request } = self.reset() else { unreachable!() };
+            return Ok(Some(request));
+          }
+          if size > self.limits.max_body - request.body.len() {
+            return Err(ParseError::BodyTooLarge);
+          }
+          if reader.rest.len() <= size {
+            return self.waiting(buf);
+          }
+          let (chunk, rest) = reader.rest.split_at(size);
+          reader.rest = rest;
+          let end = read_chunk_end(&mut reader);
+          if reader.complete(end)?.is_none() {
+            return self.waiting(buf);
+          }
+          request.body.extend_from_slice(chunk);
+          let used = buf.len() - reader.rest.len();
+          buf.drain(..used);
+        },
+      }
+    }
+  }
+
+  /* None, unless buf holds more than the request can take. until the
+  request is parsed, everything in buf belongs to it. */
+  fn waiting(&self, buf: &[u8]) -> Result<Option<Request>, ParseError> {
+    let limits = &self.limits;
+    match &self.stage {
+      Stage::Head { .. } if buf.len() > limits.max_request_line + limits.max_header_bytes + FRAMING_ALLOWANCE => {
+        Err(ParseError::HeadersTooLarge)
+      },
+      /* the trailer fields are bounded like headers */
+      Stage::Chunked { request } if request.body.len() + buf.len() > limits.max_body + limits.max_header_bytes + FRAMING_ALLOWANCE => {
+        Err(ParseError::BodyTooLarge)
+      },
+      _ => Ok(None),
+    }
+  }
+
+  /* back to waiting for a head, returns the stage left */
+  fn reset(&mut self) -> Stage {
+    mem::replace(&mut self.stage, Stage::Head { skipped: 0, scanned: 0, line_start: 0 })
+  }
+}
+
+/* bytes of the complete empty lines at the start of buf */
+fn leading_blank_lines(buf: &[u8]) -> usize {
+  let mut end = 0;
+  while let Some(line_break) = [&b"\n"[..], b"\r\n"].into_iter().find(|line_break| buf[end..].starts_with(line_break)) {
+    end += line_break.len();
+  }
+  end
+}
+
+/* a reader of the bytes received so far, noting when it runs out of them */
+struct Partial<'a> {
+  rest: &'a [u8],
+  exhausted: bool,
+}
+
+impl Partial<'_> {
+  /* the result of reading from self, None when it failed for want of more bytes */
+  fn complete<T>(&self, result: Result<T, ParseError>) -> Result<Option<T>, ParseError> {
+    match result {
+      Ok(value) => Ok(Some(value)),
+      /* whatever went wrong, it went wrong for want of more bytes */
+      Err(_) if self.exhausted => Ok(None),
+      Err(e) => Err(e),
+    }
+  }
+}
+
+impl Read for Partial<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let read = self.fill_buf()?.read(buf)?;
+    self.consume(read);
+    Ok(read)
+  }
+}
+
+impl BufRead for Partial<'_> {
+  fn fill_buf(&mut self) -> io::Result<&[u8]> {
+    self.exhausted |= self.rest.is_empty();
+    Ok(self.rest)
+  }
+
+  fn consume(&mut self, amount: usize) {
+    self.rest = &self.rest[amount..];
+  }
+}
+
+enum Line {
+  Complete(String),
+  Eof(String),
+  TooLong,
+}
+
+/* reads a line ended by "\r\n" (or a bare "\n") of at most max bytes */
+fn read_line(reader: &mut impl BufRead, max: usize) -> Result<Line, ParseError> {
+  let mut line = Vec::new();
+  /* room for the line break on top of max */
+  reader.take(max as u64 + 2).read_until(b'\n', &mut line)?;
+  let complete = line.last() == Some(&b'\n');
+  if complete {
+    line.pop();
+    if line.last() == Some(&b'\r') {
+      line.pop();
+    }
+  }
+  if line.len() > max {
+    return Ok(Line::TooLong);
+  }
+  /* a CR anywhere else could end the line for another parser */
+  if line.contains(&b'\r') {
+    return Err(ParseError::Malformed("bare CR in request head"));
+  }
+  let line = String::from_utf8(line).map_err(|_| ParseError::Malformed("request head is not valid UTF-8"))?;
+  Ok(if complete { Line::Complete(line) } else { Line::Eof(line) })
+}
+
+fn read_headers(reader: &mut impl BufRead, limits: &Limits) -> Result<Headers, ParseError> {
+  let mut headers = Headers::new();
+  let mut remaining = limits.max_header_bytes;
+  loop {
+    let line = match read_line(reader, remaining)? {
+      Line::Complete(line) => line,
+      Line::TooLong => return Err(ParseError::HeadersTooLarge),
+      Line::Eof(_) => return Err(ParseError::Malformed("truncated headers")),
+    };
+    if line.is_empty() {
+      return Ok(headers);
+    }
+    remaining -= line.len();
+    if headers.len() == limits.max_headers {
+      return Err(ParseError::HeadersTooLarge);
+    }
+    let (name, value) = line.split_once(':').ok_or(ParseError::Malformed("header without a colon"))?;
+    /* also rejects obsolete line folding, which starts with a space */
+    if !is_token(name) {
+      return Err(ParseError::Malformed("invalid header name"));
+    }
+    headers.append(name, value.trim_matches([' ', '\t']));
+  }
+}
+
+/* how the end of a body is found */
+enum Framing {
+  Length(usize),
+  Chunked,
+}
+
+/* the framing announced by the headers, no body being a length of 0 */
+fn framing(headers: &Headers, limits: &Limits) -> Result<Framing, ParseError> {
+  let lengths: Vec<&str> = headers.get_all("Content-Length").collect();
+  if let Some(encoding) = headers.get("Transfer-Encoding") {
+    /* both framings at once is how requests get smuggled */
+    if !lengths.is_empty() {
+      return Err(ParseError::Malformed("both Content-Length and Transfer-Encoding"));
+    }
+    if !encoding.eq_ignore_ascii_case("chunked") {
+      return Err(ParseError::UnsupportedTransferEncoding);
+    }
+    return Ok(Framing::Chunked);
+  }
+  let length = match lengths.first() {
+    None => return Ok(Framing::Length(0)),
+    Some(length) if lengths.iter().any(|other| other != length) => {
+      return Err(ParseError::Malformed("conflicting Content-Length headers"));
+    },
+    Some(length) if !length.is_empty() && length.bytes().all(|b| b.is_ascii_digit()) => {
+      length.parse::<usize>().map_err(|_| ParseError::BodyTooLarge)?
+    },
+    Some(_) => return Err(ParseError::Malformed("invalid Content-Length")),
+  };
+  if length > limits.max_body {
+    return Err(ParseError::BodyTooLarge);
+  }
+  Ok(Framing::Length(length))
+}
+
+fn read_chunked(reader: &mut impl BufRead, limits: &Limits) -> Result<Vec<u8>, ParseError> {
+  let mut body = Vec::new();
+  loop {
+    let size = read_chunk_size(reader)?;
+    if size == 0 {
+      break;
+    }
+    if size > limits.max_body - body.len() {
+      return Err(ParseError::BodyTooLarge);
+    }
+    let start = body.len();
+    body.resize(start + size, 0);
+    reader.read_exact(&mut body[start..]).map_err(|_| ParseError::Malformed("truncated chunk"))?;
+    read_chunk_end(reader)?;
+  }
+  /* trailer fields are read and dropped */
+  read_headers(reader, limits)?;
+  Ok(body)
+}
+
+fn read_chunk_size(reader: &mut impl BufRead) -> Result<usize, ParseError> {
+  let line = match read_line(reader, 1024)? {
+    Line::Complete(line) => line,
+    _ => return Err(ParseError::Malformed("invalid chunk size")),
+  };
+  /* chunk extensions after ';' are ignored */
+  let size = line.split(';').next().unwrap().trim_end();
+  if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return Err(ParseError::Malformed("invalid chunk size"));
+  }
+  usize::from_str_radix(size, 16).map_err(|_| ParseError::BodyTooLarge)
+}
+
+/* the line break after the data of a chunk */
+fn read_chunk_end(reader: &mut impl BufRead) -> Result<(), ParseError> {
+  match read_line(reader, 0)? {
+    Line::Complete(_) => Ok(()),
+    _ => Err(ParseError::Malformed("chunk not followed by a line break")),
+  }
+}
+
+/* None on a truncated escape or a result that is not UTF-8 */
+fn percent_decode(s: &str, plus_as_space: bool) -> Option<String> {
+  let mut bytes = Vec::with_capacity(s.len());
+  let mut input = s.bytes();
+  while let Some(b) = input.next() {
+    match b {
+      b'%' => {
+        let high = (input.next()? as char).to_digit(16)?;
+        let low = (input.next()? as char).to_digit(16)?;
+        bytes.push((high * 16 + low) as u8);
+      },
+      b'+' if plus_as_space => bytes.push(b' '),
+      _ => bytes.push(b),
+    }
+  }
+  String::from_utf8(bytes).ok()
+}
+
+fn parse_query(query: &str) -> Option<Vec<(String, String)>> {
+  query.split('&')
+    .filter(|pair| !pair.is_empty())
+    .map(|pair| {
+      let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+      Some((percent_decode(name, true)?, percent_decode(value, true)?))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(raw: &str) -> Result<Request, ParseError> {
+    Request::parse(&mut raw.as_bytes(), &Limits::default())
+  }
+
+  fn status(raw: &str) -> Option<StatusCode> {
+    parse(raw).unwrap_err().status()
+  }
+
+  #[test]
+  fn parse_head_and_query() {
+    let request = parse(
+      "\r\nGET /search%20me?q=rust+book&page=2&empty HTTP/1.1\r\nHost: localhost\r\nX-Tag:  a \r\nx-tag: b\r\n\r\n"
+    ).unwrap();
+    assert_eq!(Method::Get, request.method);
+    assert_eq!("/search me", request.path);
+    assert_eq!(Some("rust book"), request.query("q"));
+    assert_eq!(Some("2"), request.query("page"));
+    assert_eq!(Some(""), request.query("empty"));
+    assert_eq!(Some("a"), request.headers.get("x-tag"));
+    assert_eq!(vec!["a", "b"], request.headers.get_all("X-TAG").collect::<Vec<_>>());
+    assert!(request.body.is_empty());
+  }
+
+  #[test]
+  fn parse_bodies() {
+    let request = parse("POST /form HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\nhello, ignored").unwrap();
+    assert_eq!(b"hello", &request.body[..]);
+    let request = parse(
+      "POST /up HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nWiki\r\n6\r\npedia \r\n0\r\nTrailer: x\r\n\r\n"
+    ).unwrap();
+    assert_eq!(b"Wikipedia ", &request.body[..]);
+  }
+
+  /* feeds raw to a RequestParser step bytes at a time, returns the
+  requests parsed and the bytes left over */
+  fn parse_incrementally(raw: &[u8], step: usize, limits: Limits) -> Result<(Vec<Request>, Vec<u8>), ParseError> {
+    let mut parser = RequestParser::new(limits);
+    let (mut requests, mut buf) = (Vec::new(), Vec::new());
+    for piece in raw.chunks(step) {
+      buf.extend_from_slice(piece);
+      while let Some(request) = parser.parse(&mut buf)? {
+        requests.push(request);
+      }
+    }
+    Ok((requests, buf))
+  }
+
+  #[test]
+  fn parser_waits_for_whole_requests() {
+    let pipelined = concat!(
+      "POST /a HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\n\r\nabc",
+      "\r\nPOST /b HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n4;ext=1\r\nWiki\r\n6\r\npedia \r\n0\r\nTrailer: x\r\n\r\n",
+      "GET /c HTTP/1.1\r\nHost: h\r\n\r\nGET /d HTTP/1.1\r\n",
+    ).as_bytes();
+    for step in 1..=pipelined.len() {
+      let (requests, rest) = parse_incrementally(pipelined, step, Limits::default()).unwrap();
+      let parsed: Vec<_> = requests.iter().map(|request| (request.path.as_str(), &request.body[..])).collect();
+      assert_eq!(vec![("/a", &b"abc"[..]), ("/b", b"Wikipedia "), ("/c", b"")], parsed, "{} bytes at a time", step);
+      assert_eq!(b"GET /d HTTP/1.1\r\n", &rest[..]);
+    }
+    /* errors show before the request is complete */
+    let error = parse_incrementally(b"GET / HTTP/2.0\r\n", 1, Limits::default()).unwrap_err();
+    assert_eq!(Some(StatusCode::HttpVersionNotSupported), error.status());
+    let error = parse_incrementally(b"POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 2000000\r\n\r\n", 1, Limits::default());
+    assert_eq!(Some(StatusCode::PayloadTooLarge), error.unwrap_err().status());
+    let line = format!("GET /{} HTTP/1.1", "a".repeat(20_000));
+    let error = parse_incrementally(line.as_bytes(), 100, Limits::default()).unwrap_err();
+    assert_eq!(Some(StatusCode::UriTooLong), error.status());
+  }
+
+  #[test]
+  fn parser_bounds_what_it_buffers() {
+    let limits = Limits::default();
+    /* blank lines count against the request line, and are not kept */
+    let mut parser = RequestParser::new(limits);
+    let mut buf = Vec::new();
+    let error = loop {
+      buf.extend_from_slice(&b"\r\n".repeat(32));
+      match parser.parse(&mut buf) {
+        Ok(request) => assert!(request.is_none() && buf.is_empty()),
+        Err(e) => break e,
+      }
+    };
+    assert_eq!(Some(StatusCode::UriTooLong), error.status());
+    let (requests, _) = parse_incrementally(b"\r\n\r\nGET / HTTP/1.1\r\nHost: h\r\n\r\n", 1, limits).unwrap();
+    assert_eq!(1, requests.len());
+    assert!(matches!(parse(&format!("{}GET / HTTP/1.1\r\nHost: h\r\n\r\n", "\r\n".repeat(5000))), Err(ParseError::UriTooLong)));
+
+    /* the size lines of chunks are dropped once parsed */
+    let mut parser = RequestParser::new(limits);
+    let mut buf = b"POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    let chunk = format!("1;{}\r\nx\r\n", "e".repeat(1000));
+    for _ in 0..5000 {
+      buf.extend_from_slice(chunk.as_bytes());
+      assert!(parser.parse(&mut buf).unwrap().is_none());
+      assert!(buf.len() < chunk.len());
+    }
+    buf.extend_from_slice(b"0\r\n\r\n");
+    assert_eq!(vec![b'x'; 5000], parser.parse(&mut buf).unwrap().unwrap().body);
+    assert!(buf.is_empty());
+  }
+
+  #[test]
+  fn parser_takes_large_bodies_arriving_slowly() {
+    /* parsing everything received on every read would copy tens of gigabytes here */
+    let limits = Limits::default();
+    let body: Vec<u8> = (0..limits.max_body).map(|i| i as u8).collect();
+    let mut raw = format!("POST /a HTTP/1.1\r\nHost: h\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+    raw.extend(&body);
+    raw.extend(b"POST /b HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n");
+    for chunk in body.chunks(limits.max_body / 4) {
+      raw.extend(format!("{:x}\r\n", chunk.len()).as_bytes());
+      raw.extend(chunk);
+      raw.extend(b"\r\n");
+    }
+    raw.extend(b"0\r\n\r\n");
+    let (requests, rest) = parse_incrementally(&raw, 16, limits).unwrap();
+    assert_eq!(2, requests.len());
+    assert!(requests.iter().all(|request| request.body == body));
+    assert!(rest.is_empty());
+  }
+
+  #[test]
+  fn keep_alive_follows_version_and_connection() {
+    assert!(parse("GET / HTTP/1.1\r\nHost: h\r\n\r\n").unwrap().keep_alive());
+    assert!(!parse("GET / HTTP/1.1\r\nHost: h\r\nConnection: Upgrade, close\r\n\r\n").unwrap().keep_alive());
+    assert!(!parse("GET / HTTP/1.0\r\n\r\n").unwrap().keep_alive());
+    assert!(parse("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").unwrap().keep_alive());
+  }
+
+  #[test]
+  fn parse_leaves_pipelined_requests_unread() {
+    let mut raw = "GET /a HTTP/1.1\r\nHost: h\r\n\r\nGET /b HTTP/1.1\r\nHost: h\r\n\r\n".as_bytes();
+    assert_eq!("/a", Request::parse(&mut raw, &Limits::default()).unwrap().path);
+    assert_eq!("/b", Request::parse(&mut raw, &Limits::default()).unwrap().path);
+    assert!(matches!(Request::parse(&mut raw, &Limits::default()), Err(ParseError::Closed)));
+  }
+
+  #[test]
+  fn parse_rejects_malformed_requests() {
+    let bad = Some(StatusCode::BadRequest);
+    assert_eq!(bad, status("GET /\r\n\r\n"));
+    assert_eq!(bad, status("GET / HTTP/1.1\r\n\r\n"));
+    assert_eq!(bad, status("GET example.com HTTP/1.1\r\nHost: h\r\n\r\n"));
+    assert_eq!(bad, status("GET /%zz HTTP/1.1\r\nHost: h\r\n\r\n"));
+    assert_eq!(bad, status("GET / HTTP/1.1\r\nHost: h\r\n folded\r\n\r\n"));
+    assert_eq!(bad, status("GET / HTTP/1.1\r\nHost h\r\n\r\n"));
+    assert_eq!(bad, status("GET /a\rb HTTP/1.1\r\nHost: h\r\n\r\n"));
+    assert_eq!(bad, status("GET / HTTP/1.1\r\nHost: h\rX: 1\r\n\r\n"));
+    assert_eq!(bad, status("POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 10\r\n\r\nshort"));
+    assert_eq!(bad, status("POST / HTTP/1.1\r\nHost: h\r\nContent-Length: -1\r\n\r\n"));
+    assert_eq!(bad, status("POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"));
+    assert_eq!(
+      bad,
+      status("POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n")
+    );
+    assert_eq!(bad, status("POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"));
+    assert_eq!(Some(StatusCode::HttpVersionNotSupported), status("GET / HTTP/2.0\r\n\r\n"));
+    assert_eq!(
+      Some(StatusCode::NotImplemented),
+      status("POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: gzip\r\n\r\n")
+    );
+    assert!(matches!(parse(""), Err(ParseError::Closed)));
+  }
+
+  #[test]
+  fn parse_enforces_limits() {
+    let limits = Limits { max_request_line: 16, max_header_bytes: 40, max_headers: 2, max_body: 4 };
+    let parse = |raw: &str| Request::parse(&mut raw.as_bytes(), &limits).unwrap_err().status();
+    assert_eq!(Some(StatusCode::UriTooLong), parse("GET /a-very-long-path HTTP/1.1\r\n"));
+    assert_eq!(
+      Some(StatusCode::RequestHeaderFieldsTooLarge),
+      parse(&format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", "h".repeat(40)))
+    );
+    assert_eq!(Some(StatusCode::RequestHeaderFieldsTooLarge), parse("GET / HTTP/1.1\r\nHost: h\r\nA: 1\r\nB: 2\r\n\r\n"));
+    assert_eq!(Some(StatusCode::PayloadTooLarge), parse("POST / HTTP/1.1\r\nHost: h\r\nContent-Length: 5\r\n\r\n"));
+    assert_eq!(
+      Some(StatusCode::PayloadTooLarge),
+      parse("POST / HTTP/1.1\r\nHost: h\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n")
+    );
+  }
+}
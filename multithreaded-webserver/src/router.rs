@@ -0,0 +1,226 @@
+//! routing of requests to handlers by method and path pattern.
+//! patterns are made of '/' separated segments:
+//!   /cv           a literal segment
+//!   /users/:id    `:name` captures one segment
+//!   /files/*path  `*name` (or a bare `*`) captures the rest of the path,
+//!                 it must be the last segment
+//! routes are tried in registration order and the first match wins.
+use crate::{
+  http::{Method, Response, StatusCode},
+  request::Request,
+};
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync + 'static>;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+  Literal(String),
+  Param(String),
+  Rest(Option<String>),
+}
+
+struct Route {
+  method: Method,
+  pattern: Vec<Segment>,
+  handler: Handler,
+}
+
+/* the segments captured by a route pattern */
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self.0.iter()
+      .find(|(param, _)| param == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+pub struct Router {
+  routes: Vec<Route>,
+  not_found: Handler,
+}
+
+impl Default for Router {
+  fn default() -> Self {
+    Router::new()
+  }
+}
+
+impl Router {
+  pub fn new() -> Self {
+    Router {
+      routes: Vec::new(),
+      not_found: Box::new(|_, _| Response::html(StatusCode::NotFound, "Not Found")),
+    }
+  }
+
+  /* panics on a pattern with a wildcard before its last segment */
+  pub fn route<F>(&mut self, method: Method, pattern: &str, handler: F) -> &mut Self
+  where
+    F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+  {
+    let pattern = parse_pattern(pattern);
+    let rest = pattern.iter().position(|segment| matches!(segment, Segment::Rest(_)));
+    assert!(rest.is_none_or(|position| position == pattern.len() - 1), "wildcards must end a route pattern");
+    self.routes.push(Route { method, pattern, handler: Box::new(handler) });
+    self
+  }
+
+  pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+  where
+    F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+  {
+    self.route(Method::Get, pattern, handler)
+  }
+
+  pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+  where
+    F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+  {
+    self.route(Method::Post, pattern, handler)
+  }
+
+  /* answers requests no route matches, a plain 404 by default */
+  pub fn not_found<F>(&mut self, handler: F) -> &mut Self
+  where
+    F: Fn(&Request, &Params) -> Response + Send + Sync + 'static
+  {
+    self.not_found = Box::new(handler);
+    self
+  }
+
+  /* runs the first route matching the request, GET routes answering HEAD
+  too. a path matched by routes of other methods only is answered with 405
+  and an Allow header. */
+  pub fn handle(&self, request: &Request) -> Response {
+    let mut allowed: Vec<&str> = Vec::new();
+    for route in &self.routes {
+      if let Some(params) = match_path(&route.pattern, &request.path) {
+        if route.method == request.method || (route.method == Method::Get && request.method == Method::Head) {
+          return (route.handler)(request, &params);
+        }
+        let methods = match &route.method {
+          Method::Get => vec!["GET", "HEAD"],
+          method => vec![method.as_str()],
+        };
+        for method in methods {
+          if !allowed.contains(&method) {
+            allowed.push(method);
+          }
+        }
+      }
+    }
+    if allowed.is_empty() {
+      (self.not_found)(request, &Params::default())
+    } else {
+      Response::new(StatusCode::MethodNotAllowed).with_header("Allow", &allowed.join(", "))
+    }
+  }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+  path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+  segments(pattern)
+    .map(|segment| {
+      if let Some(name) = segment.strip_prefix(':') {
+        Segment::Param(name.to_string())
+      } else if let Some(name) = segment.strip_prefix('*') {
+        Segment::Rest((!name.is_empty()).then(|| name.to_string()))
+      } else {
+        Segment::Literal(segment.to_string())
+      }
+    })
+    .collect()
+}
+
+fn match_path(pattern: &[Segment], path: &str) -> Option<Params> {
+  let mut params = Vec::new();
+  let mut path_segments = segments(path);
+  for segment in pattern {
+    match segment {
+      Segment::Literal(literal) => {
+        if path_segments.next()? != literal {
+          return None;
+        }
+      },
+      Segment::Param(name) => params.push((name.clone(), path_segments.next()?.to_string())),
+      Segment::Rest(name) => {
+        let rest = path_segments.by_ref().collect::<Vec<_>>().join("/");
+        if let Some(name) = name {
+          params.push((name.clone(), rest));
+        }
+      },
+    }
+  }
+  match path_segments.next() {
+    Some(_) => None,
+    None => Some(Params(params)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(method: &str, path: &str) -> Request {
+    let raw = format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    Request::parse(&mut raw.as_bytes(), &Default::default()).unwrap()
+  }
+
+  fn body(response: Response) -> String {
+    String::from_utf8(response.body.into_bytes().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn match_params_and_wildcards() {
+    let pattern = parse_pattern("/users/:id/files/*path");
+    assert_eq!(
+      Some(Params(vec![("id".into(), "7".into()), ("path".into(), "a/b.txt".into())])),
+      match_path(&pattern, "/users/7/files/a/b.txt")
+    );
+    assert_eq!(None, match_path(&pattern, "/users/7"));
+    assert_eq!(Some(Params::default()), match_path(&parse_pattern("/"), "/"));
+    assert_eq!(None, match_path(&parse_pattern("/cv"), "/cv/more"));
+    assert_eq!(Some(Params::default()), match_path(&parse_pattern("/static/*"), "/static"));
+  }
+
+  #[test]
+  fn handle_dispatches_by_method_and_order() {
+    let mut router = Router::new();
+    router
+      .get("/users/me", |_, _| Response::html(StatusCode::Ok, "me"))
+      .get("/users/:id", |_, params| Response::html(StatusCode::Ok, params.get("id").unwrap()))
+      .post("/users", |_, _| Response::html(StatusCode::Ok, "created"));
+    assert_eq!("me", body(router.handle(&request("GET", "/users/me"))));
+    assert_eq!("42", body(router.handle(&request("GET", "/users/42?x=1"))));
+    assert_eq!("created", body(router.handle(&request("POST", "/users"))));
+    let response = router.handle(&request("DELETE", "/users/42"));
+    assert_eq!(StatusCode::MethodNotAllowed, response.status);
+    assert_eq!(Some("GET, HEAD"), response.headers.get("allow"));
+    assert_eq!(StatusCode::NotFound, router.handle(&request("GET", "/nowhere")).status);
+  }
+
+  #[test]
+  fn get_routes_answer_head() {
+    let mut router = Router::new();
+    router
+      .get("/users/:id", |request, params| Response::html(StatusCode::Ok, format!("{} {}", request.method, params.get("id").unwrap())))
+      .post("/users", |_, _| Response::html(StatusCode::Ok, "created"));
+    /* the handler answers as for GET, dropping the body is left to sending */
+    assert_eq!("HEAD 42", body(router.handle(&request("HEAD", "/users/42"))));
+    let response = router.handle(&request("HEAD", "/users"));
+    assert_eq!(StatusCode::MethodNotAllowed, response.status);
+    assert_eq!(Some("POST"), response.headers.get("allow"));
+  }
+
+  #[test]
+  #[should_panic(expected = "wildcards must end")]
+  fn route_rejects_inner_wildcards() {
+    Router::new().get("/*/x", |_, _| Response::new(StatusCode::Ok));
+  }
+}
@@ -0,0 +1,168 @@
+//! throughput and queueing latency of the work-stealing ThreadPool
+//! against the design it replaced, where every worker locked one
+//! Mutex<mpsc::Receiver> to get its next job.
+//!
+//! each workload submits its jobs from a single thread, like the webserver's
+//! accept loop does. latency is the time from execute to the job starting;
+//! throughput counts jobs from the first execute to the last job done.
+//!
+//!   cargo bench --bench pool
+use std::{
+  hint::black_box,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    mpsc, Arc, Mutex
+  },
+  thread,
+  time::{Duration, Instant}
+};
+
+use thread_pool::{PoolOptions, ThreadPool};
+
+const ROUNDS: usize = 5;
+
+/* the previous pool, kept as the baseline */
+struct MutexPool {
+  workers: Vec<thread::JoinHandle<()>>,
+  sender: Option<mpsc::Sender<Job>>,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+impl MutexPool {
+  fn new(pool_size: usize) -> Self {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let workers = (0..pool_size)
+      .map(|_| {
+        let receiver = Arc::clone(&receiver);
+        thread::spawn(move || loop {
+          let message = receiver.lock().unwrap().recv();
+          match message {
+            Ok(job) => job(),
+            Err(_) => break,
+          }
+        })
+      })
+      .collect();
+    MutexPool { workers, sender: Some(sender) }
+  }
+
+  fn execute(&mut self, job: impl FnOnce() + Send + 'static) {
+    self.sender.as_ref().unwrap().send(Box::new(job)).unwrap();
+  }
+}
+
+impl Drop for MutexPool {
+  fn drop(&mut self) {
+    drop(self.sender.take());
+    for worker in self.workers.drain(..) {
+      worker.join().unwrap();
+    }
+  }
+}
+
+/* what both pools offer the benchmark */
+trait Pool {
+  fn execute(&mut self, job: impl FnOnce() + Send + 'static);
+}
+
+impl Pool for MutexPool {
+  fn execute(&mut self, job: impl FnOnce() + Send + 'static) {
+    MutexPool::execute(self, job)
+  }
+}
+
+impl Pool for ThreadPool {
+  fn execute(&mut self, job: impl FnOnce() + Send + 'static) {
+    ThreadPool::execute(self, job);
+  }
+}
+
+struct Workload {
+  name: &'static str,
+  jobs: usize,
+  /* busy time of job i */
+  work: fn(usize) -> Duration,
+}
+
+fn spin(duration: Duration) {
+  let start = Instant::now();
+  while start.elapsed() < duration {
+    black_box(());
+  }
+}
+
+/* runs the workload once, returns the throughput and the latencies */
+fn round<P: Pool>(mut pool: P, workload: &Workload) -> (f64, Vec<u64>) {
+  let latencies: Arc<Vec<AtomicU64>> = Arc::new((0..workload.jobs).map(|_| AtomicU64::new(0)).collect());
+  let start = Instant::now();
+  for i in 0..workload.jobs {
+    let latencies = Arc::clone(&latencies);
+    let work = (workload.work)(i);
+    let queued = Instant::now();
+    pool.execute(move || {
+      latencies[i].store(queued.elapsed().as_nanos() as u64, Ordering::Relaxed);
+      spin(work);
+    });
+  }
+  drop(pool); /* both pools finish the queued jobs before dropping */
+  let throughput = workload.jobs as f64 / start.elapsed().as_secs_f64();
+  (throughput, latencies.iter().map(|latency| latency.load(Ordering::Relaxed)).collect())
+}
+
+fn percentile(sorted: &[u64], p: f64) -> Duration {
+  let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+  Duration::from_nanos(sorted[index])
+}
+
+fn report<P: Pool>(design: &str, workload: &Workload, new_pool: impl Fn() -> P) {
+  let mut throughputs = Vec::new();
+  let mut latencies = Vec::new();
+  for _ in 0..ROUNDS {
+    let (throughput, round_latencies) = round(new_pool(), workload);
+    throughputs.push(throughput);
+    latencies.extend(round_latencies);
+  }
+  throughputs.sort_by(f64::total_cmp);
+  latencies.sort_unstable();
+  println!(
+    "{:<14} {:<9} {:>12.0} {:>10.1?} {:>10.1?} {:>10.1?} {:>10.1?}",
+    workload.name,
+    design,
+    throughputs[ROUNDS / 2],
+    percentile(&latencies, 0.5),
+    percentile(&latencies, 0.99),
+    percentile(&latencies, 0.999),
+    latencies.last().map(|max| Duration::from_nanos(*max)).unwrap(),
+  );
+}
+
+fn main() {
+  /* cargo test runs benches once with --bench absent: keep that quick */
+  if !std::env::args().any(|arg| arg == "--bench") {
+    return;
+  }
+  let cores = thread::available_parallelism().map_or(4, usize::from);
+  let workloads = [
+    Workload { name: "empty", jobs: 100_000, work: |_| Duration::ZERO },
+    Workload { name: "10us", jobs: 50_000, work: |_| Duration::from_micros(10) },
+    /* one job in a hundred is slow, the rest trivial */
+    Workload {
+      name: "skewed",
+      jobs: 20_000,
+      work: |i| if i % 100 == 0 { Duration::from_micros(500) } else { Duration::from_micros(2) },
+    },
+  ];
+  for pool_size in [4, cores.max(2)] {
+    println!("\n{} workers, median of {} rounds for throughput", pool_size, ROUNDS);
+    println!(
+      "{:<14} {:<9} {:>12} {:>10} {:>10} {:>10} {:>10}",
+      "workload", "design", "jobs/s", "p50", "p99", "p99.9", "max"
+    );
+    for workload in &workloads {
+      report("mutex", workload, || MutexPool::new(pool_size));
+      report("stealing", workload, || ThreadPool::new(PoolOptions::fixed(pool_size)));
+    }
+  }
+}
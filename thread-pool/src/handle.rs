@@ -0,0 +1,141 @@
+//! results of jobs. every job hands its result, or the message of its
+//! panic, to the JobHandle returned when it was queued.
+use std::{
+  error::Error,
+  fmt,
+  panic::{self, AssertUnwindSafe},
+  sync::{Arc, Condvar, Mutex, PoisonError},
+  time::{Duration, Instant}
+};
+
+use crate::{lock, panic_message};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+  Panicked(String), /* the message the job panicked with */
+  Lost, /* the job was dropped without running */
+}
+
+impl fmt::Display for JobError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      JobError::Panicked(message) => write!(f, "job panicked: {}", message),
+      JobError::Lost => write!(f, "job was dropped before it ran"),
+    }
+  }
+}
+
+impl Error for JobError {}
+
+/* where a job leaves its result for the handle */
+struct Slot<T> {
+  result: Mutex<Option<Result<T, JobError>>>,
+  ready: Condvar,
+}
+
+/* dropping a handle detaches the job: it still runs, its result is dropped */
+pub struct JobHandle<T> {
+  slot: Arc<Slot<T>>,
+}
+
+impl<T> JobHandle<T> {
+  /* waits for the job to finish */
+  pub fn join(self) -> Result<T, JobError> {
+    let mut result = lock(&self.slot.result);
+    loop {
+      match result.take() {
+        Some(result) => return result,
+        None => result = self.slot.ready.wait(result).unwrap_or_else(PoisonError::into_inner),
+      }
+    }
+  }
+
+  /* waits at most timeout for the job, gives the handle back if it is still running */
+  pub fn join_timeout(self, timeout: Duration) -> Result<Result<T, JobError>, Self> {
+    let deadline = Instant::now() + timeout;
+    let mut result = lock(&self.slot.result);
+    loop {
+      if let Some(result) = result.take() {
+        return Ok(result);
+      }
+      let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+        drop(result);
+        return Err(self);
+      };
+      result = self.slot.ready.wait_timeout(result, left).unwrap_or_else(PoisonError::into_inner).0;
+    }
+  }
+
+  pub fn is_finished(&self) -> bool {
+    lock(&self.slot.result).is_some()
+  }
+}
+
+impl<T> fmt::Debug for JobHandle<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("JobHandle").field("finished", &self.is_finished()).finish()
+  }
+}
+
+/* the job's end of the slot. dropped unfulfilled, the job is lost */
+struct Promise<T> {
+  slot: Arc<Slot<T>>,
+}
+
+impl<T> Promise<T> {
+  fn fulfil(&self, value: Result<T, JobError>) {
+    let mut result = lock(&self.slot.result);
+    if result.is_none() {
+      *result = Some(value);
+      self.slot.ready.notify_all();
+    }
+  }
+}
+
+impl<T> Drop for Promise<T> {
+  fn drop(&mut self) {
+    self.fulfil(Err(JobError::Lost));
+  }
+}
+
+/* wraps fun into a job reporting to the returned handle. a panic is
+reported, then resumed for the worker to recover from like any other. */
+pub(crate) fn job<'a, F, T>(fun: F) -> (JobHandle<T>, Box<dyn FnOnce() + Send + 'a>)
+where
+  F: FnOnce() -> T + Send + 'a,
+  T: Send + 'a
+{
+  let slot = Arc::new(Slot { result: Mutex::new(None), ready: Condvar::new() });
+  let promise = Promise { slot: Arc::clone(&slot) };
+  let job = Box::new(move || match panic::catch_unwind(AssertUnwindSafe(fun)) {
+    Ok(value) => promise.fulfil(Ok(value)),
+    Err(payload) => {
+      promise.fulfil(Err(JobError::Panicked(panic_message(&*payload).to_string())));
+      panic::resume_unwind(payload)
+    },
+  });
+  (JobHandle { slot }, job)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn handles_see_results_panics_and_lost_jobs() {
+    let (handle, run) = job(|| 6 * 7);
+    assert!(!handle.is_finished());
+    run();
+    assert!(handle.is_finished());
+    assert_eq!(Ok(42), handle.join());
+
+    let (handle, run) = job(|| -> u8 { panic!("no answer") });
+    assert!(panic::catch_unwind(AssertUnwindSafe(run)).is_err());
+    assert_eq!(Err(JobError::Panicked("no answer".to_string())), handle.join());
+
+    let (handle, run) = job(|| ());
+    let handle = handle.join_timeout(Duration::from_millis(10)).unwrap_err();
+    drop(run);
+    assert_eq!(Err(JobError::Lost), handle.join());
+  }
+}
@@ -0,0 +1,738 @@
+//! a pool of worker threads running jobs in the background.
+//! execute queues a job and returns a JobHandle to wait for its result;
+//! scope queues jobs borrowing from the caller's stack and waits for
+//! them; join_all waits until every queued job is done.
+//!
+//! jobs are pushed on a global injector queue. an idle worker takes a
+//! batch of them into its own deque and works through it; a worker that
+//! runs out of jobs steals from the injector, then from the other
+//! workers' deques. unlike the single Mutex<Receiver> the pool used to
+//! share, workers mostly touch their own deque and only meet on steals.
+//! idle workers sleep on a condition variable until jobs arrive.
+use std::{
+  any::Any,
+  fmt,
+  iter,
+  panic::{self, AssertUnwindSafe},
+  thread,
+  sync::{
+    atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    mpsc, Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock
+  },
+  time::{Duration, Instant}
+};
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker as Deque};
+
+mod handle;
+mod scope;
+
+pub use handle::{JobError, JobHandle};
+pub use scope::Scope;
+
+/* sizing of a ThreadPool. it starts with min_workers, adds one (up to
+max_workers) whenever jobs stayed queued longer than grow_after, and
+workers idle for idle_timeout, which must not be zero, retire down to
+min_workers. */
+#[derive(Debug, Clone, Copy)]
+pub struct PoolOptions {
+  pub min_workers: usize,
+  pub max_workers: usize,
+  /* None for an unbounded queue. besides capacity queued jobs, the pool
+  accepts one job per idle worker, so 0 only accepts jobs a worker can
+  start right away */
+  pub queue_capacity: Option<usize>,
+  pub grow_after: Duration,
+  pub idle_timeout: Duration,
+  /* print workers starting, retiring, dying and shutting down to stdout */
+  pub verbose: bool,
+}
+
+impl PoolOptions {
+  /* a pool of pool_size workers, never resized */
+  pub fn fixed(pool_size: usize) -> Self {
+    PoolOptions {
+      min_workers: pool_size,
+      max_workers: pool_size,
+      queue_capacity: None,
+      grow_after: Duration::from_millis(200),
+      idle_timeout: Duration::from_secs(60),
+      verbose: false,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+  pub workers: usize,
+  pub busy: usize,
+  pub queued: usize, /* jobs waiting for a worker */
+}
+
+impl fmt::Display for PoolStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} workers ({} busy), {} queued jobs", self.workers, self.busy, self.queued)
+  }
+}
+
+/* the job was refused because the queue is at capacity */
+#[derive(Debug)]
+pub struct QueueFull;
+
+pub(crate) type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/* state of the pool seen by the workers and the supervisor */
+pub(crate) struct Shared {
+  options: PoolOptions,
+  injector: Injector<Job>,
+  stealers: RwLock<Vec<(usize, Stealer<Job>)>>,
+  workers: Mutex<Vec<WorkerThread>>,
+  queued: AtomicUsize,
+  size: AtomicUsize, /* workers not retired */
+  busy: AtomicUsize,
+  pending: AtomicUsize, /* jobs queued and not finished yet */
+  /* nanoseconds from started to when the queue last stopped being empty */
+  backlog_since: AtomicU64,
+  started: Instant,
+  closed: AtomicBool,
+  /* idle workers wait on work, producers of a full queue on room and
+  join_all on idle */
+  sleepers: AtomicUsize,
+  waking: AtomicBool,
+  producers_waiting: AtomicUsize,
+  sleep: Mutex<()>,
+  work: Condvar,
+  room: Condvar,
+  idle: Condvar,
+  events: mpsc::Sender<Event>,
+}
+
+impl Shared {
+  fn take_job(&self, deque: &Deque<Job>) -> Option<Job> {
+    let job = deque.pop().or_else(|| iter::repeat_with(|| {
+      self.injector.steal_batch_and_pop(deque)
+        .or_else(|| lock_read(&self.stealers).iter().map(|(_, stealer)| stealer.steal()).collect())
+    })
+      .find(|steal| !steal.is_retry())
+      .and_then(Steal::success))?;
+    if self.queued.fetch_sub(1, Ordering::SeqCst) > 1 {
+      self.wake_one();
+    }
+    if self.producers_waiting.load(Ordering::SeqCst) > 0 {
+      let _sleep = lock(&self.sleep);
+      self.room.notify_all();
+    }
+    Some(job)
+  }
+
+  fn restart_backlog(&self) {
+    self.backlog_since.store(self.started.elapsed().as_nanos() as u64, Ordering::SeqCst);
+  }
+
+  /* how long jobs have been queued without the queue ever emptying,
+  the wait of the oldest job without keeping a time per job */
+  fn backlog(&self) -> Duration {
+    if self.queued.load(Ordering::SeqCst) == 0 {
+      return Duration::ZERO;
+    }
+    let since = Duration::from_nanos(self.backlog_since.load(Ordering::SeqCst));
+    self.started.elapsed().saturating_sub(since)
+  }
+
+  /* whether the queue can take one more job */
+  fn has_room(&self) -> bool {
+    match self.options.queue_capacity {
+      None => true,
+      Some(capacity) => {
+        let idle = self.size.load(Ordering::SeqCst).saturating_sub(self.busy.load(Ordering::SeqCst));
+        self.queued.load(Ordering::SeqCst) < capacity + idle
+      },
+    }
+  }
+
+  /* waits until the queue can take one more job */
+  fn wait_for_room(&self) {
+    if self.has_room() {
+      return;
+    }
+    let mut sleep = lock(&self.sleep);
+    self.producers_waiting.fetch_add(1, Ordering::SeqCst);
+    while !self.has_room() {
+      /* woken when a worker takes a job, the timeout covers the idle
+      workers that appear when a job ends */
+      sleep = self.room.wait_timeout(sleep, Duration::from_millis(10))
+        .unwrap_or_else(PoisonError::into_inner).0;
+    }
+    self.producers_waiting.fetch_sub(1, Ordering::SeqCst);
+  }
+
+  fn push(&self, job: Job) {
+    self.pending.fetch_add(1, Ordering::SeqCst);
+    if self.queued.fetch_add(1, Ordering::SeqCst) == 0 {
+      self.restart_backlog();
+    }
+    self.injector.push(job);
+    self.wake_one();
+  }
+
+  /* wakes a sleeping worker, unless one is already being woken: that one
+  wakes the next if it finds more jobs than it can take. a worker about to
+  sleep counts itself before looking at queued one last time, so either
+  it sees the new job or we see it sleeping. */
+  fn wake_one(&self) {
+    if self.sleepers.load(Ordering::SeqCst) > 0 && !self.waking.swap(true, Ordering::SeqCst) {
+      let _sleep = lock(&self.sleep);
+      self.work.notify_one();
+    }
+  }
+
+  fn finished(&self) {
+    if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+      let _sleep = lock(&self.sleep);
+      self.idle.notify_all();
+    }
+  }
+
+  fn log(&self, message: fmt::Arguments) {
+    if self.options.verbose {
+      println!("{}", message);
+    }
+  }
+
+  /* moves the jobs left in a gone worker's deque back to the injector */
+  fn reclaim(&self, id: usize) {
+    let mut stealers = lock_write(&self.stealers);
+    if let Some(position) = stealers.iter().position(|(worker, _)| *worker == id) {
+      let (_, stealer) = stealers.swap_remove(position);
+      loop {
+        match stealer.steal() {
+          Steal::Success(job) => self.injector.push(job),
+          Steal::Empty => break,
+          Steal::Retry => (),
+        }
+      }
+    }
+  }
+}
+
+pub struct ThreadPool {
+  shared: Arc<Shared>,
+  supervisor: Option<Supervisor>,
+}
+
+impl Drop for ThreadPool {
+  /* all threads should join a locked state
+  to finish their jobs before being droped
+  by the main thread. */
+  fn drop(&mut self) {
+    self.close();
+    for worker in lock(&self.shared.workers).iter_mut() {
+      /* call take on option to get the val in Some(val) and
+      leave None value in the place of Option<thread::JoinHandle<()>. */
+      if let Some(thread) = worker.thread.take() {
+        self.shared.log(format_args!("Shutting down worker {}", worker.id));
+        join(&self.shared, worker.id, thread);
+      }
+    }
+  }
+}
+
+impl ThreadPool {
+  pub fn new(options: PoolOptions) -> Self {
+    assert!(options.min_workers>0);
+    assert!(options.min_workers <= options.max_workers);
+    assert!(options.idle_timeout > Duration::ZERO);
+    let (events, deaths) = mpsc::channel();
+    let shared = Arc::new(Shared {
+      options,
+      injector: Injector::new(),
+      stealers: RwLock::new(Vec::new()),
+      workers: Mutex::new(Vec::new()),
+      queued: AtomicUsize::new(0),
+      size: AtomicUsize::new(options.min_workers),
+      busy: AtomicUsize::new(0),
+      pending: AtomicUsize::new(0),
+      backlog_since: AtomicU64::new(0),
+      started: Instant::now(),
+      closed: AtomicBool::new(false),
+      sleepers: AtomicUsize::new(0),
+      waking: AtomicBool::new(false),
+      producers_waiting: AtomicUsize::new(0),
+      sleep: Mutex::new(()),
+      work: Condvar::new(),
+      room: Condvar::new(),
+      idle: Condvar::new(),
+      events: events.clone(),
+    });
+    let workers = (0..options.min_workers).map(|id| WorkerThread::spawn(id, &shared)).collect();
+    *lock(&shared.workers) = workers;
+    let supervisor = Supervisor::spawn(Arc::clone(&shared), events, deaths);
+    ThreadPool { shared, supervisor: Some(supervisor) }
+  }
+
+  /* queues the job, waiting for room in a full bounded queue */
+  pub fn execute<F, T>(&mut self, fun: F) -> JobHandle<T>
+  where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static
+  {
+    self.shared.wait_for_room();
+    let (handle, job) = handle::job(fun);
+    self.shared.push(job);
+    handle
+  }
+
+  /* queues the job unless the queue is full, the job is dropped then */
+  pub fn try_execute<F, T>(&mut self, fun: F) -> Result<JobHandle<T>, QueueFull>
+  where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static
+  {
+    /* only this thread adds jobs (&mut self), so the room stays */
+    if !self.shared.has_room() {
+      return Err(QueueFull);
+    }
+    let (handle, job) = handle::job(fun);
+    self.shared.push(job);
+    Ok(handle)
+  }
+
+  /* waits until every job queued so far has finished, the pool stays open */
+  pub fn join_all(&self) {
+    let mut sleep = lock(&self.shared.sleep);
+    while self.shared.pending.load(Ordering::SeqCst) > 0 {
+      sleep = self.shared.idle.wait(sleep).unwrap_or_else(PoisonError::into_inner);
+    }
+  }
+
+  pub fn stats(&self) -> PoolStats {
+    PoolStats {
+      workers: self.shared.size.load(Ordering::SeqCst),
+      busy: self.shared.busy.load(Ordering::SeqCst),
+      queued: self.shared.queued.load(Ordering::SeqCst),
+    }
+  }
+
+  /* closes the queue and waits for the workers to finish the queued
+  jobs. workers still busy after timeout are left running detached,
+  returns how many of them there were. */
+  pub fn shutdown(mut self, timeout: Duration) -> usize {
+    self.close();
+    let deadline = Instant::now() + timeout;
+    let running = |workers: &[WorkerThread]| workers.iter()
+      .filter_map(|worker| worker.thread.as_ref())
+      .any(|thread| !thread.is_finished());
+    while Instant::now() < deadline && running(&lock(&self.shared.workers)) {
+      thread::sleep(Duration::from_millis(10));
+    }
+    let mut abandoned = 0;
+    for worker in lock(&self.shared.workers).iter_mut() {
+      match worker.thread.take() {
+        Some(thread) if thread.is_finished() => join(&self.shared, worker.id, thread),
+        Some(_) => {
+          self.shared.log(format_args!("Worker {} is still busy; abandoning it.", worker.id));
+          abandoned += 1;
+        },
+        None => (),
+      }
+    }
+    abandoned
+  }
+
+  /* stops the supervisor, then lets the workers finish once the queue is empty */
+  fn close(&mut self) {
+    if let Some(supervisor) = self.supervisor.take() {
+      let _ = supervisor.events.send(Event::Stop);
+      let _ = supervisor.thread.join();
+    }
+    self.shared.closed.store(true, Ordering::SeqCst);
+    let _sleep = lock(&self.shared.sleep);
+    self.shared.work.notify_all();
+  }
+}
+
+struct WorkerThread {
+  id: usize,
+  thread: Option<thread::JoinHandle<()>>
+}
+
+impl WorkerThread {
+  fn spawn(id: usize, shared: &Arc<Shared>) -> Self {
+    let deque = Deque::new_fifo();
+    lock_write(&shared.stealers).push((id, deque.stealer()));
+    let shared = Arc::clone(shared);
+    let thread = thread::Builder::new().name(format!("worker-{id}")).spawn(move || {
+      let _sentinel = Sentinel { id, events: shared.events.clone() };
+      let mut idle_since = Instant::now();
+      loop { /* loop waiting for new jobs */
+        if let Some(job) = shared.take_job(&deque) {
+          shared.busy.fetch_add(1, Ordering::SeqCst);
+          /* a panicking job must not take the worker down with it */
+          let result = panic::catch_unwind(AssertUnwindSafe(job));
+          shared.busy.fetch_sub(1, Ordering::SeqCst);
+          shared.finished();
+          if let Err(payload) = result {
+            shared.log(format_args!("Worker {id} recovered from a panicking job: {}", panic_message(&*payload)));
+          }
+          idle_since = Instant::now();
+          continue;
+        }
+        if shared.closed.load(Ordering::SeqCst) && shared.queued.load(Ordering::SeqCst) == 0 {
+          shared.log(format_args!("Worker {id} disconnected; shutting down."));
+          break; /* exit loop once the pool is closed and every job is done. */
+        }
+        let idle = idle_since.elapsed();
+        if idle >= shared.options.idle_timeout {
+          let min_workers = shared.options.min_workers;
+          let retired = shared.size.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |size| {
+            (size > min_workers).then(|| size - 1)
+          });
+          if retired.is_ok() {
+            shared.log(format_args!("Worker {id} idle; retiring."));
+            let _ = shared.events.send(Event::Retired(id));
+            break;
+          }
+          idle_since = Instant::now();
+          continue;
+        }
+        let sleep = lock(&shared.sleep);
+        shared.sleepers.fetch_add(1, Ordering::SeqCst);
+        /* a wake up delivered while nobody waited must not block the next */
+        shared.waking.store(false, Ordering::SeqCst);
+        if shared.queued.load(Ordering::SeqCst) == 0 && !shared.closed.load(Ordering::SeqCst) {
+          let _ = shared.work.wait_timeout(sleep, shared.options.idle_timeout - idle);
+          shared.waking.store(false, Ordering::SeqCst);
+        }
+        shared.sleepers.fetch_sub(1, Ordering::SeqCst);
+      }
+    });
+    let thread = Some(thread.expect("failed to spawn a worker thread"));
+    WorkerThread { id, thread }
+  }
+}
+
+enum Event {
+  Died(usize),
+  Retired(usize),
+  Stop,
+}
+
+/* reports its worker to the supervisor if the thread unwinds */
+struct Sentinel {
+  id: usize,
+  events: mpsc::Sender<Event>,
+}
+
+impl Drop for Sentinel {
+  fn drop(&mut self) {
+    if thread::panicking() {
+      let _ = self.events.send(Event::Died(self.id));
+    }
+  }
+}
+
+/* replaces the workers that died, so the pool never shrinks below its
+size, reaps retired workers and adds workers when jobs wait too long */
+struct Supervisor {
+  thread: thread::JoinHandle<()>,
+  events: mpsc::Sender<Event>,
+}
+
+impl Supervisor {
+  fn spawn(shared: Arc<Shared>, events: mpsc::Sender<Event>, deaths: mpsc::Receiver<Event>) -> Self {
+    let tick = (shared.options.grow_after / 4).clamp(Duration::from_millis(1), Duration::from_millis(100));
+    let thread = thread::spawn(move || {
+      let mut next_id = shared.options.min_workers;
+      loop {
+        match deaths.recv_timeout(tick) {
+          Ok(Event::Died(id)) => {
+            shared.reclaim(id);
+            let mut workers = lock(&shared.workers);
+            let Some(worker) = workers.iter_mut().find(|worker| worker.id == id) else { continue };
+            if let Some(Err(payload)) = worker.thread.take().map(|thread| thread.join()) {
+              shared.log(format_args!("Worker {id} died: {}", panic_message(&*payload)));
+            }
+            shared.log(format_args!("Respawning worker {id}."));
+            *worker = WorkerThread::spawn(id, &shared);
+          },
+          Ok(Event::Retired(id)) => {
+            shared.reclaim(id);
+            let mut workers = lock(&shared.workers);
+            if let Some(position) = workers.iter().position(|worker| worker.id == id) {
+              if let Some(thread) = workers.swap_remove(position).thread {
+                join(&shared, id, thread);
+              }
+            }
+          },
+          Ok(Event::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+          Err(mpsc::RecvTimeoutError::Timeout) => (),
+        }
+        if shared.backlog() > shared.options.grow_after {
+          let max_workers = shared.options.max_workers;
+          let grown = shared.size.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |size| {
+            (size < max_workers).then(|| size + 1)
+          });
+          if grown.is_ok() {
+            shared.log(format_args!("Jobs are waiting; adding worker {next_id}."));
+            lock(&shared.workers).push(WorkerThread::spawn(next_id, &shared));
+            next_id += 1;
+            /* the next worker only if jobs keep waiting that long again */
+            shared.restart_backlog();
+          }
+        }
+      }
+    });
+    Supervisor { thread, events }
+  }
+}
+
+fn join(shared: &Shared, id: usize, thread: thread::JoinHandle<()>) {
+  if thread.join().is_err() {
+    shared.log(format_args!("Worker {id} died while shutting down."));
+  }
+}
+
+/* a panic while a lock was held leaves the guarded value usable here:
+the worker list and the stealers have no invariant a panic could break */
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn lock_read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+  lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn lock_write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+  lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+  match payload.downcast_ref::<&str>() {
+    Some(message) => message,
+    None => payload.downcast_ref::<String>().map_or("unknown panic", String::as_str),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shutdown_drains_queued_jobs() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    let done = Arc::new(AtomicUsize::new(0));
+    for _ in 0..6 {
+      let done = Arc::clone(&done);
+      pool.execute(move || {
+        thread::sleep(Duration::from_millis(20));
+        done.fetch_add(1, Ordering::SeqCst);
+      });
+    }
+    assert_eq!(0, pool.shutdown(Duration::from_secs(5)));
+    assert_eq!(6, done.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn shutdown_gives_up_on_stuck_jobs() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    pool.execute(|| thread::sleep(Duration::from_secs(2)));
+    pool.execute(|| ());
+    let start = Instant::now();
+    assert_eq!(1, pool.shutdown(Duration::from_millis(100)));
+    assert!(start.elapsed() < Duration::from_secs(1));
+  }
+
+  #[test]
+  fn abandoned_workers_still_finish_their_jobs() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    /* held until released below, whatever the timeout */
+    let (release, released) = mpsc::channel::<()>();
+    let stuck = pool.execute(move || released.recv());
+    assert_eq!(Ok(()), pool.execute(|| ()).join());
+    assert_eq!(1, pool.shutdown(Duration::from_millis(100)));
+    assert!(!stuck.is_finished());
+    release.send(()).unwrap();
+    assert_eq!(Ok(Ok(())), stuck.join());
+  }
+
+  #[test]
+  fn jobs_behind_an_abandoned_worker_still_run() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(1));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let (release, released) = mpsc::channel::<()>();
+    let mut released = Some(released);
+    let handles: Vec<_> = (0..3).map(|i| {
+      let order = Arc::clone(&order);
+      /* the first job holds the only worker until released */
+      let released = released.take();
+      pool.execute(move || {
+        if let Some(released) = released {
+          released.recv().unwrap();
+        }
+        lock(&order).push(i);
+      })
+    }).collect();
+    assert_eq!(1, pool.shutdown(Duration::from_millis(20)));
+    assert!(lock(&order).is_empty());
+    release.send(()).unwrap();
+    for handle in handles {
+      assert_eq!(Ok(()), handle.join());
+    }
+    assert_eq!(vec![0, 1, 2], *lock(&order));
+  }
+
+  #[test]
+  fn a_single_worker_runs_jobs_in_order() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(1));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..100 {
+      let order = Arc::clone(&order);
+      pool.execute(move || lock(&order).push(i));
+    }
+    assert_eq!(0, pool.shutdown(Duration::from_secs(1)));
+    assert_eq!((0..100).collect::<Vec<_>>(), *lock(&order));
+  }
+
+  #[test]
+  fn dropping_the_pool_finishes_queued_jobs_first() {
+    let done = Arc::new(AtomicUsize::new(0));
+    let handles: Vec<_> = {
+      let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+      (0..6).map(|_| {
+        let done = Arc::clone(&done);
+        pool.execute(move || {
+          thread::sleep(Duration::from_millis(20));
+          done.fetch_add(1, Ordering::SeqCst)
+        })
+      }).collect()
+    };
+    assert_eq!(6, done.load(Ordering::SeqCst));
+    assert!(handles.iter().all(JobHandle::is_finished));
+    let mut results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    results.sort();
+    assert_eq!(vec![0, 1, 2, 3, 4, 5], results);
+  }
+
+  #[test]
+  fn handles_return_results_and_panics() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    let squares: Vec<_> = (0..10u64).map(|n| pool.execute(move || n * n)).collect();
+    let failed = pool.execute(|| -> u64 { panic!("job failed") });
+    let squares: Vec<_> = squares.into_iter().map(|square| square.join().unwrap()).collect();
+    assert_eq!(285, squares.iter().sum::<u64>());
+    assert_eq!(Err(JobError::Panicked("job failed".to_string())), failed.join());
+    assert_eq!(Ok("still working"), pool.execute(|| "still working").join());
+  }
+
+  #[test]
+  fn join_all_waits_for_running_and_queued_jobs() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    let done = Arc::new(AtomicUsize::new(0));
+    for round in 1..=2 {
+      for _ in 0..5 {
+        let done = Arc::clone(&done);
+        pool.execute(move || {
+          thread::sleep(Duration::from_millis(10));
+          done.fetch_add(1, Ordering::SeqCst);
+        });
+      }
+      pool.join_all();
+      assert_eq!(5 * round, done.load(Ordering::SeqCst));
+      assert_eq!(PoolStats { workers: 2, busy: 0, queued: 0 }, pool.stats());
+    }
+    pool.join_all(); /* nothing queued: returns at once */
+  }
+
+  /* runs a job on each of the pool_size workers at once */
+  fn on_every_worker(pool: &mut ThreadPool, pool_size: usize) -> usize {
+    let barrier = Arc::new(std::sync::Barrier::new(pool_size + 1));
+    let done = Arc::new(AtomicUsize::new(0));
+    for _ in 0..pool_size {
+      let (barrier, done) = (Arc::clone(&barrier), Arc::clone(&done));
+      pool.execute(move || {
+        barrier.wait();
+        done.fetch_add(1, Ordering::SeqCst);
+      });
+    }
+    barrier.wait();
+    thread::sleep(Duration::from_millis(50));
+    done.load(Ordering::SeqCst)
+  }
+
+  #[test]
+  fn panicking_jobs_keep_workers_alive() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    for _ in 0..4 {
+      pool.execute(|| panic!("job failed"));
+    }
+    assert_eq!(2, on_every_worker(&mut pool, 2));
+  }
+
+  #[test]
+  fn bounded_queue_refuses_jobs_when_full() {
+    let mut pool = ThreadPool::new(PoolOptions { queue_capacity: Some(1), ..PoolOptions::fixed(1) });
+    let (release, gate) = mpsc::channel::<()>();
+    let (started, running) = mpsc::channel();
+    pool.execute(move || {
+      started.send(()).unwrap();
+      gate.recv().unwrap();
+    });
+    running.recv().unwrap(); /* the worker is busy, the queue empty */
+    assert!(pool.try_execute(|| ()).is_ok());
+    assert!(pool.try_execute(|| ()).is_err());
+    release.send(()).unwrap();
+    pool.execute(|| ()); /* blocks until the worker takes the queued job */
+    assert_eq!(0, pool.shutdown(Duration::from_secs(1)));
+  }
+
+  /* polls stats until they satisfy done, for at most two seconds */
+  fn wait_for(pool: &ThreadPool, done: impl Fn(PoolStats) -> bool) -> PoolStats {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !done(pool.stats()) && Instant::now() < deadline {
+      thread::sleep(Duration::from_millis(10));
+    }
+    pool.stats()
+  }
+
+  #[test]
+  fn pool_grows_under_load_and_shrinks_when_idle() {
+    let mut pool = ThreadPool::new(PoolOptions {
+      min_workers: 1,
+      max_workers: 3,
+      queue_capacity: None,
+      grow_after: Duration::from_millis(20),
+      idle_timeout: Duration::from_millis(100),
+      verbose: false,
+    });
+    let gate = Arc::new(Mutex::new(()));
+    let held = lock(&gate);
+    for _ in 0..4 {
+      let gate = Arc::clone(&gate);
+      pool.execute(move || drop(lock(&gate)));
+    }
+    /* three workers stuck on the gate, the fourth job still queued */
+    let stats = wait_for(&pool, |stats| stats.busy == 3);
+    assert_eq!(PoolStats { workers: 3, busy: 3, queued: 1 }, stats);
+    drop(held);
+    let stats = wait_for(&pool, |stats| stats.workers == 1);
+    assert_eq!(PoolStats { workers: 1, busy: 0, queued: 0 }, stats);
+    assert_eq!(0, pool.shutdown(Duration::from_secs(1)));
+  }
+
+  #[test]
+  fn dead_workers_are_respawned() {
+    /* dropping this payload panics outside of catch_unwind, killing the worker */
+    struct Bomb;
+    impl Drop for Bomb {
+      fn drop(&mut self) {
+        panic!("payload exploded");
+      }
+    }
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    let bomb = pool.execute(|| panic::panic_any(Bomb));
+    assert_eq!(Err(JobError::Panicked("unknown panic".to_string())), bomb.join());
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(2, on_every_worker(&mut pool, 2));
+    assert_eq!(0, pool.shutdown(Duration::from_secs(1)));
+  }
+}
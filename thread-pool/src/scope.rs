@@ -0,0 +1,173 @@
+//! jobs borrowing from the stack of the thread that queues them.
+//! ThreadPool::scope only returns once every job queued in the scope has
+//! finished, the same promise std::thread::scope makes for threads, so
+//! the borrows of its jobs outlive them.
+use std::{
+  marker::PhantomData,
+  mem,
+  panic::{self, AssertUnwindSafe},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex, PoisonError
+  },
+  thread
+};
+
+use crate::{handle, lock, Job, JobHandle, Shared, ThreadPool};
+
+/* the jobs of a scope still running */
+struct Running {
+  count: Mutex<usize>,
+  done: Condvar,
+  panicked: AtomicBool,
+}
+
+/* counts its job out of the scope when the job is over, however it ends */
+struct Done(Arc<Running>);
+
+impl Drop for Done {
+  fn drop(&mut self) {
+    if thread::panicking() {
+      self.0.panicked.store(true, Ordering::SeqCst);
+    }
+    let mut count = lock(&self.0.count);
+    *count -= 1;
+    if *count == 0 {
+      self.0.done.notify_all();
+    }
+  }
+}
+
+pub struct Scope<'scope, 'env: 'scope> {
+  shared: Arc<Shared>,
+  running: Arc<Running>,
+  /* invariant in both lifetimes, like std::thread::Scope */
+  scope: PhantomData<&'scope mut &'scope ()>,
+  env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+  /* queues a job that may borrow anything outliving the scope, waiting
+  for room in a full bounded queue. jobs of the scope may queue more. */
+  pub fn execute<F, T>(&'scope self, fun: F) -> JobHandle<T>
+  where
+    F: FnOnce() -> T + Send + 'scope,
+    T: Send + 'scope
+  {
+    self.shared.wait_for_room();
+    *lock(&self.running.count) += 1;
+    let done = Done(Arc::clone(&self.running));
+    let (handle, job) = handle::job(fun);
+    let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+      let _done = done; /* dropped last, once job and its captures are gone */
+      job()
+    });
+    /* SAFETY: the job only lives past 'scope in the queue and in a worker;
+    scope waits for it to run and be dropped (counted by Done) before
+    anything it borrows can go away. */
+    let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+    self.shared.push(job);
+    handle
+  }
+}
+
+impl ThreadPool {
+  /* runs f with a scope for jobs borrowing from the caller, then waits
+  for all of them. panics if f or any of the jobs did, after the wait.
+  called from a job of this pool, it ties up that worker while waiting. */
+  pub fn scope<'env, F, R>(&mut self, f: F) -> R
+  where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+  {
+    let scope = Scope {
+      shared: Arc::clone(&self.shared),
+      running: Arc::new(Running { count: Mutex::new(0), done: Condvar::new(), panicked: AtomicBool::new(false) }),
+      scope: PhantomData,
+      env: PhantomData,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    let mut count = lock(&scope.running.count);
+    while *count > 0 {
+      count = scope.running.done.wait(count).unwrap_or_else(PoisonError::into_inner);
+    }
+    drop(count);
+    match result {
+      Err(payload) => panic::resume_unwind(payload),
+      Ok(_) if scope.running.panicked.load(Ordering::SeqCst) => panic!("a scoped job panicked"),
+      Ok(value) => value,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::PoolOptions;
+  use std::time::Duration;
+
+  #[test]
+  fn scoped_jobs_borrow_from_the_stack() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(3));
+    let numbers: Vec<u64> = (1..=1000).collect();
+    let mut squares = vec![0; numbers.len()];
+    let total = pool.scope(|scope| {
+      for (chunk, squares) in numbers.chunks(100).zip(squares.chunks_mut(100)) {
+        scope.execute(move || {
+          for (square, n) in squares.iter_mut().zip(chunk) {
+            *square = n * n;
+          }
+        });
+      }
+      let sums: Vec<_> = numbers.chunks(300).map(|chunk| scope.execute(move || chunk.iter().sum::<u64>())).collect();
+      sums.into_iter().map(|sum| sum.join().unwrap()).sum::<u64>()
+    });
+    assert_eq!(500_500, total);
+    assert_eq!(Some(&1_000_000), squares.last());
+    assert!(squares.iter().zip(&numbers).all(|(square, n)| *square == n * n));
+  }
+
+  #[test]
+  fn scope_waits_for_nested_jobs() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    let finished = AtomicBool::new(false);
+    pool.scope(|scope| {
+      scope.execute(|| {
+        thread::sleep(Duration::from_millis(20));
+        scope.execute(|| {
+          thread::sleep(Duration::from_millis(20));
+          finished.store(true, Ordering::SeqCst);
+        });
+      });
+    });
+    assert!(finished.load(Ordering::SeqCst));
+  }
+
+  #[test]
+  fn scope_waits_for_its_jobs_before_panicking() {
+    let mut pool = ThreadPool::new(PoolOptions::fixed(2));
+    let finished = AtomicBool::new(false);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| pool.scope(|scope| {
+      scope.execute(|| {
+        thread::sleep(Duration::from_millis(50));
+        finished.store(true, Ordering::SeqCst);
+      });
+      panic!("scope failed");
+    })));
+    assert!(result.is_err());
+    assert!(finished.load(Ordering::SeqCst));
+
+    /* a panicking job makes the scope panic once the others are done */
+    let finished = AtomicBool::new(false);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| pool.scope(|scope| {
+      scope.execute(|| panic!("job failed"));
+      scope.execute(|| {
+        thread::sleep(Duration::from_millis(50));
+        finished.store(true, Ordering::SeqCst);
+      });
+    })));
+    assert!(result.is_err());
+    assert!(finished.load(Ordering::SeqCst));
+    /* the pool survives both */
+    assert_eq!(Ok(3), pool.execute(|| 3).join());
+  }
+}